@@ -0,0 +1,46 @@
+#![feature(test)]
+
+extern crate test;
+
+use maud::Escaper;
+use std::fmt::Write;
+
+/// Prose with no special characters.
+const PLAIN: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do \
+    eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim \
+    veniam, quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo \
+    consequat. Duis aute irure dolor in reprehenderit in voluptate velit esse cillum.";
+
+/// Markup-heavy text, where most words need escaping.
+const SPECIAL: &str = r#"<p class="intro">Fish & chips</p><a href="/?a=1&b=2">"Go" -></a>"#;
+
+/// Mostly plain text with the occasional special character.
+const MIXED: &str = "Tom & Jerry is a series of short films created in 1940 by William \
+    Hanna and Joseph Barbera. It centers on a rivalry between the title characters: \
+    Tom, a cat, and Jerry, a mouse. Many shorts also feature \"Spike\" the bulldog, \
+    whose catchphrase is <\"That's my boy!\">, and who is Tom's usual nemesis.";
+
+fn escape(b: &mut test::Bencher, input: &str) {
+    let input = test::black_box(input.repeat(100));
+    b.bytes = input.len() as u64;
+    b.iter(|| {
+        let mut s = String::with_capacity(input.len() * 2);
+        Escaper::new(&mut s).write_str(&input).unwrap();
+        s
+    });
+}
+
+#[bench]
+fn escape_plain(b: &mut test::Bencher) {
+    escape(b, PLAIN);
+}
+
+#[bench]
+fn escape_special(b: &mut test::Bencher) {
+    escape(b, SPECIAL);
+}
+
+#[bench]
+fn escape_mixed(b: &mut test::Bencher) {
+    escape(b, MIXED);
+}
@@ -0,0 +1,233 @@
+//! Caching for fragments of markup that are expensive to render.
+//!
+//! A [`Cache`] holds rendered markup, keyed by any hashable value. To
+//! render through it, splice a [`Cached`] from [`Cache::entry`]: the
+//! first time a key is seen, its markup is rendered and stored, and
+//! later renders of that key copy the stored markup instead.
+//!
+//! # Example
+//!
+//! ```rust
+//! use maud::cache::Cache;
+//! use maud::html;
+//! use std::time::Duration;
+//!
+//! let menus = Cache::new(16).with_ttl(Duration::from_secs(60));
+//!
+//! let page = |user: &str| html! {
+//!     (menus.entry("main", || html! {
+//!         nav { a href="/" { "Home" } }
+//!     }))
+//!     p { "Hello, " (user) }
+//! };
+//!
+//! assert_eq!(page("Ferris").into_string(), r#"<nav><a href="/">Home</a></nav><p>Hello, Ferris</p>"#);
+//! assert_eq!(menus.len(), 1);
+//!
+//! // After the menu changes
+//! menus.invalidate(&"main");
+//! ```
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::{Markup, Render};
+
+/// A bounded cache of rendered markup.
+///
+/// When the cache is full, the least recently used entry is removed to
+/// make room. Entries can also expire after a fixed time, with
+/// [`Cache::with_ttl`].
+///
+/// A `Cache` can be shared between threads, for example in an `Arc` or
+/// a `static` `OnceLock`.
+pub struct Cache<K> {
+    state: Mutex<State<K>>,
+    capacity: usize,
+    ttl: Option<Duration>,
+}
+
+struct State<K> {
+    entries: HashMap<K, Entry>,
+    /// Counts up on every use, to tell which entry was used least recently.
+    clock: u64,
+}
+
+struct Entry {
+    markup: String,
+    created: Instant,
+    last_used: u64,
+}
+
+impl<K: Hash + Eq> Cache<K> {
+    /// Creates a cache that holds up to `capacity` entries.
+    pub fn new(capacity: usize) -> Cache<K> {
+        Cache {
+            state: Mutex::new(State {
+                entries: HashMap::new(),
+                clock: 0,
+            }),
+            capacity,
+            ttl: None,
+        }
+    }
+
+    /// Makes entries expire once they are older than `ttl`.
+    ///
+    /// An expired entry is rendered again the next time it is used.
+    pub fn with_ttl(mut self, ttl: Duration) -> Cache<K> {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Returns a value that renders through this cache.
+    ///
+    /// On a miss, `render` is called and its result is stored under
+    /// `key`. On a hit, the stored markup is used instead.
+    pub fn entry<F: Fn() -> Markup>(&self, key: K, render: F) -> Cached<'_, K, F> {
+        Cached {
+            cache: self,
+            key,
+            render,
+        }
+    }
+
+    /// Removes the entry for the given key, so that it is rendered
+    /// again next time.
+    pub fn invalidate<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lock().entries.remove(key);
+    }
+
+    /// Removes every entry whose key doesn't match the predicate.
+    pub fn retain(&self, mut predicate: impl FnMut(&K) -> bool) {
+        self.lock().entries.retain(|key, _| predicate(key));
+    }
+
+    /// Removes every entry.
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    /// Returns the number of entries, including expired ones that have
+    /// not been removed yet.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Returns whether the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends the markup for `key` to `buffer`, rendering it first if
+    /// needed.
+    fn render_to(&self, key: &K, render: impl FnOnce() -> Markup, buffer: &mut String)
+    where
+        K: Clone,
+    {
+        {
+            let mut state = self.lock();
+            state.clock += 1;
+            let clock = state.clock;
+            if let Some(entry) = state.entries.get_mut(key) {
+                if !self.is_expired(entry) {
+                    entry.last_used = clock;
+                    buffer.push_str(&entry.markup);
+                    return;
+                }
+            }
+        }
+        // Render without holding the lock, since it may take a while
+        let markup = render().into_string();
+        buffer.push_str(&markup);
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.lock();
+        if !state.entries.contains_key(key) {
+            state.make_room(self.capacity, self.ttl);
+        }
+        let last_used = state.clock;
+        state.entries.insert(
+            key.clone(),
+            Entry {
+                markup,
+                created: Instant::now(),
+                last_used,
+            },
+        );
+    }
+
+    fn is_expired(&self, entry: &Entry) -> bool {
+        self.ttl.is_some_and(|ttl| entry.created.elapsed() >= ttl)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<K>> {
+        // A panic while rendering can't leave the map in a bad state
+        self.state.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+impl<K: Hash + Eq + Clone> State<K> {
+    /// Makes room for one more entry, by removing the expired entries,
+    /// or if there are none, the least recently used one.
+    fn make_room(&mut self, capacity: usize, ttl: Option<Duration>) {
+        if self.entries.len() < capacity {
+            return;
+        }
+        if let Some(ttl) = ttl {
+            self.entries
+                .retain(|_, entry| entry.created.elapsed() < ttl);
+            if self.entries.len() < capacity {
+                return;
+            }
+        }
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+impl<K> fmt::Debug for Cache<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+/// A fragment that renders through a [`Cache`].
+///
+/// This is returned by [`Cache::entry`]. A cache hit costs one lookup
+/// and one copy of the stored markup.
+pub struct Cached<'a, K, F> {
+    cache: &'a Cache<K>,
+    key: K,
+    render: F,
+}
+
+impl<'a, K: Hash + Eq + Clone, F: Fn() -> Markup> Render for Cached<'a, K, F> {
+    fn render_to(&self, buffer: &mut String) {
+        self.cache.render_to(&self.key, &self.render, buffer);
+    }
+}
+
+impl<'a, K: fmt::Debug, F> fmt::Debug for Cached<'a, K, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cached").field("key", &self.key).finish()
+    }
+}
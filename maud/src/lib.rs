@@ -7,9 +7,15 @@
 
 #![doc(html_root_url = "https://docs.rs/maud/0.22.2")]
 
+use std::borrow::Cow;
 use std::fmt::{self, Write};
+use std::io;
+use std::iter::FromIterator;
+use std::ops;
 
-pub use maud_macros::{html, html_debug};
+pub use maud_macros::{html, html_debug, html_pretty, html_static, html_to, try_html};
+
+pub mod cache;
 
 /// Represents a type that can be rendered as HTML.
 ///
@@ -67,12 +73,129 @@ pub trait Render {
     fn render_to(&self, buffer: &mut String) {
         buffer.push_str(&self.render().into_string());
     }
+
+    /// Appends a representation of `self` to the given buffer, escaped
+    /// for the given [`Context`](struct.Context.html).
+    ///
+    /// The `html!` macro calls this method for every splice, with a
+    /// context that depends on where the splice appears: an element,
+    /// an attribute, a URL, a `script` element, and so on.
+    ///
+    /// Its default implementation just calls `.render_to()`, and
+    /// passes the result through as trusted markup. Types that
+    /// implement `Display` escape their text for the context instead.
+    fn render_to_context(&self, context: Context, buffer: &mut String) {
+        let _ = context;
+        self.render_to(buffer);
+    }
 }
 
 impl<T: fmt::Display + ?Sized> Render for T {
     fn render_to(&self, w: &mut String) {
         let _ = write!(Escaper::new(w), "{}", self);
     }
+
+    fn render_to_context(&self, context: Context, w: &mut String) {
+        let mut escaper = ContextEscaper::new(context, w);
+        let _ = write!(escaper, "{}", self);
+        escaper.finish();
+    }
+}
+
+/// Represents a type that can be rendered as HTML, but might fail.
+///
+/// This is like [`Render`], but for types that need to do work that
+/// can fail while rendering, such as reading a file. When spliced into
+/// a [`try_html!`](macro.try_html.html) template, an error ends the
+/// template early and is returned from it.
+///
+/// Like `Render`, an implementation must override at least one of
+/// `.try_render()` or `.try_render_to()`.
+///
+/// # Example
+///
+/// ```rust
+/// use maud::{html, try_html, Markup, TryRender};
+/// use std::fs;
+/// use std::io;
+///
+/// /// Renders the contents of a text file.
+/// struct TextFile(&'static str);
+///
+/// impl TryRender for TextFile {
+///     type Error = io::Error;
+///
+///     fn try_render(&self) -> io::Result<Markup> {
+///         let text = fs::read_to_string(self.0)?;
+///         Ok(html! { pre { (text) } })
+///     }
+/// }
+///
+/// let page: io::Result<Markup> = try_html! {
+///     h1 { "Notes" }
+///     (TextFile("/does/not/exist.txt"))
+/// };
+/// assert!(page.is_err());
+/// ```
+pub trait TryRender {
+    /// The type of error that rendering can return.
+    type Error;
+
+    /// Renders `self` as a block of `Markup`, or returns an error.
+    fn try_render(&self) -> Result<Markup, Self::Error> {
+        let mut buffer = String::new();
+        self.try_render_to(&mut buffer)?;
+        Ok(PreEscaped(buffer))
+    }
+
+    /// Appends a representation of `self` to the given buffer, or
+    /// returns an error.
+    ///
+    /// Its default implementation just calls `.try_render()`. As with
+    /// [`Render::render_to`], no further escaping is performed on data
+    /// written to the buffer. If this returns an error, anything already
+    /// written is kept, but `try_html!` discards it along with the rest
+    /// of the template.
+    fn try_render_to(&self, buffer: &mut String) -> Result<(), Self::Error> {
+        buffer.push_str(&self.try_render()?.into_string());
+        Ok(())
+    }
+
+    /// Appends a representation of `self` to the given buffer, escaped
+    /// for the given [`Context`](struct.Context.html), or returns an
+    /// error.
+    ///
+    /// This is the fallible counterpart of [`Render::render_to_context`].
+    /// Its default implementation just calls `.try_render_to()`, and
+    /// passes the result through as trusted markup.
+    fn try_render_to_context(
+        &self,
+        context: Context,
+        buffer: &mut String,
+    ) -> Result<(), Self::Error> {
+        let _ = context;
+        self.try_render_to(buffer)
+    }
+}
+
+impl<T: TryRender + ?Sized> TryRender for &T {
+    type Error = T::Error;
+
+    fn try_render(&self) -> Result<Markup, Self::Error> {
+        (**self).try_render()
+    }
+
+    fn try_render_to(&self, buffer: &mut String) -> Result<(), Self::Error> {
+        (**self).try_render_to(buffer)
+    }
+
+    fn try_render_to_context(
+        &self,
+        context: Context,
+        buffer: &mut String,
+    ) -> Result<(), Self::Error> {
+        (**self).try_render_to_context(context, buffer)
+    }
 }
 
 /// Spicy hack to specialize `Render` for `T: AsRef<str>`.
@@ -88,31 +211,492 @@ impl<T: fmt::Display + ?Sized> Render for T {
 /// [1]: https://github.com/dtolnay/case-studies/issues/14
 #[doc(hidden)]
 pub mod render {
-    use crate::Render;
-    use maud_htmlescape::Escaper;
+    use crate::{PreEscaped, Render, TryRender};
+    use maud_htmlescape::{Context, ContextEscaper, Position};
     use std::fmt::Write;
+    use std::ops::Range;
 
     pub trait RenderInternal {
-        fn __maud_render_to(&self, w: &mut String);
+        fn __maud_render_to(&self, context: Context, w: &mut String);
+
+        fn __maud_try_render_to<E>(&self, context: Context, w: &mut String) -> Result<(), E> {
+            self.__maud_render_to(context, w);
+            Ok(())
+        }
     }
 
     pub struct RenderWrapper<'a, T: ?Sized>(pub &'a T);
 
     impl<'a, T: AsRef<str> + ?Sized> RenderWrapper<'a, T> {
-        pub fn __maud_render_to(&self, w: &mut String) {
-            let _ = Escaper::new(w).write_str(self.0.as_ref());
+        pub fn __maud_render_to(&self, context: Context, w: &mut String) {
+            let mut escaper = ContextEscaper::new(context, w);
+            let _ = escaper.write_str(self.0.as_ref());
+            escaper.finish();
+        }
+
+        pub fn __maud_try_render_to<E>(&self, context: Context, w: &mut String) -> Result<(), E> {
+            self.__maud_render_to(context, w);
+            Ok(())
+        }
+    }
+
+    /// Renders a `TryRender` type in `try_html!`.
+    ///
+    /// This takes `self` by value, so that it has the highest priority:
+    /// a type that implements both `TryRender` and `Render` is rendered
+    /// with `TryRender`.
+    pub trait TryRenderInternal {
+        type Error;
+
+        fn __maud_try_render_to<E: From<Self::Error>>(
+            self,
+            context: Context,
+            w: &mut String,
+        ) -> Result<(), E>;
+    }
+
+    impl<'a, T: TryRender + ?Sized> TryRenderInternal for RenderWrapper<'a, T> {
+        type Error = T::Error;
+
+        fn __maud_try_render_to<E: From<T::Error>>(
+            self,
+            context: Context,
+            w: &mut String,
+        ) -> Result<(), E> {
+            self.0.try_render_to_context(context, w).map_err(E::from)
+        }
+    }
+
+    /// Renders `PreEscaped` values as-is.
+    ///
+    /// `PreEscaped<T>` implements `AsRef<str>`, so without this it would
+    /// be escaped by the inherent method above. This takes `self` by
+    /// value, so that it has a higher priority.
+    pub trait RenderPreEscaped {
+        fn __maud_render_to(self, context: Context, w: &mut String);
+
+        fn __maud_try_render_to<E>(self, context: Context, w: &mut String) -> Result<(), E>
+        where
+            Self: Sized,
+        {
+            self.__maud_render_to(context, w);
+            Ok(())
+        }
+    }
+
+    impl<'a, T: IsPreEscaped + ?Sized> RenderPreEscaped for RenderWrapper<'a, T> {
+        fn __maud_render_to(self, _: Context, w: &mut String) {
+            w.push_str(self.0.__maud_as_str());
+        }
+    }
+
+    /// Implemented for `PreEscaped` and references to it.
+    pub trait IsPreEscaped {
+        fn __maud_as_str(&self) -> &str;
+    }
+
+    impl<T: AsRef<str>> IsPreEscaped for PreEscaped<T> {
+        fn __maud_as_str(&self) -> &str {
+            self.0.as_ref()
+        }
+    }
+
+    impl<T: IsPreEscaped + ?Sized> IsPreEscaped for &T {
+        fn __maud_as_str(&self) -> &str {
+            (**self).__maud_as_str()
+        }
+    }
+
+    /// Renders numbers and booleans as bare JavaScript literals.
+    ///
+    /// Other types are quoted as strings in a JavaScript expression, even
+    /// if their text looks like a number. This takes `self` by value, so
+    /// that it has a higher priority than `RenderInternal`.
+    pub trait RenderJsLiteral {
+        fn __maud_render_to(self, context: Context, w: &mut String);
+
+        fn __maud_try_render_to<E>(self, context: Context, w: &mut String) -> Result<(), E>
+        where
+            Self: Sized,
+        {
+            self.__maud_render_to(context, w);
+            Ok(())
+        }
+    }
+
+    impl<'a, T: IsJsLiteral + ?Sized> RenderJsLiteral for RenderWrapper<'a, T> {
+        fn __maud_render_to(self, context: Context, w: &mut String) {
+            self.0.render_to_context(context.as_js_literal(), w);
         }
     }
 
+    /// Implemented for number and boolean types, and references to them.
+    pub trait IsJsLiteral: std::fmt::Display {}
+
+    macro_rules! impl_is_js_literal {
+        ($($ty:ty)*) => {
+            $(impl IsJsLiteral for $ty {})*
+        };
+    }
+
+    impl_is_js_literal! {
+        i8 i16 i32 i64 i128 isize
+        u8 u16 u32 u64 u128 usize
+        f32 f64 bool
+    }
+
+    impl<T: IsJsLiteral + ?Sized> IsJsLiteral for &T {}
+
+    /// Renders options and collections of numbers and booleans, such as
+    /// `Some(5)` or `vec![1, 2]`, with bare JavaScript literals inside.
+    macro_rules! impl_render_js_literal_collection {
+        ($([$($generics:tt)*] $ty:ty;)*) => {
+            $(impl<'a, $($generics)*> RenderJsLiteral for RenderWrapper<'a, $ty> {
+                fn __maud_render_to(self, context: Context, w: &mut String) {
+                    RenderCollection::__maud_render_to(&self, context.as_js_literal(), w);
+                }
+            })*
+        };
+    }
+
+    impl_render_js_literal_collection! {
+        [T: IsJsLiteral] Option<T>;
+        [T: IsJsLiteral] [T];
+        [T: IsJsLiteral, const N: usize] [T; N];
+        [T: IsJsLiteral] Vec<T>;
+        ['b, T: IsJsLiteral] &'b Option<T>;
+        ['b, T: IsJsLiteral] &'b [T];
+        ['b, T: IsJsLiteral, const N: usize] &'b [T; N];
+        ['b, T: IsJsLiteral] &'b Vec<T>;
+    }
+
     impl<'a, T: Render + ?Sized> RenderInternal for RenderWrapper<'a, T> {
-        fn __maud_render_to(&self, w: &mut String) {
-            self.0.render_to(w);
+        fn __maud_render_to(&self, context: Context, w: &mut String) {
+            self.0.render_to_context(context, w);
+        }
+    }
+
+    /// Renders `Option`, `Result`, and collections of values.
+    ///
+    /// In JavaScript code, `None` renders `null`, and collections render
+    /// as an array.
+    ///
+    /// These can't implement `Render` directly, since the standard
+    /// library might implement `Display` for them one day. None of them
+    /// implement `Display` now, so this never conflicts with
+    /// `RenderInternal`.
+    pub trait RenderCollection {
+        fn __maud_render_to(&self, context: Context, w: &mut String);
+
+        fn __maud_try_render_to<E>(&self, context: Context, w: &mut String) -> Result<(), E> {
+            self.__maud_render_to(context, w);
+            Ok(())
+        }
+    }
+
+    impl<'a, T: Render> RenderCollection for RenderWrapper<'a, Option<T>> {
+        fn __maud_render_to(&self, context: Context, w: &mut String) {
+            match self.0 {
+                Some(value) => value.render_to_context(context, w),
+                None if context.position() == Position::JsValue => w.push_str("null"),
+                None => {}
+            }
+        }
+    }
+
+    impl<'a, T: Render, E: Render> RenderCollection for RenderWrapper<'a, Result<T, E>> {
+        fn __maud_render_to(&self, context: Context, w: &mut String) {
+            match self.0 {
+                Ok(value) => value.render_to_context(context, w),
+                Err(error) => error.render_to_context(context, w),
+            }
+        }
+    }
+
+    impl<'a, T: Render> RenderCollection for RenderWrapper<'a, [T]> {
+        fn __maud_render_to(&self, context: Context, w: &mut String) {
+            let mut items = ItemWriter::new(context, w);
+            for item in self.0 {
+                items.write(item);
+            }
+            items.finish();
+        }
+    }
+
+    impl<'a, T: Render, const N: usize> RenderCollection for RenderWrapper<'a, [T; N]> {
+        fn __maud_render_to(&self, context: Context, w: &mut String) {
+            RenderWrapper(&self.0[..]).__maud_render_to(context, w);
+        }
+    }
+
+    impl<'a, T: Render> RenderCollection for RenderWrapper<'a, Vec<T>> {
+        fn __maud_render_to(&self, context: Context, w: &mut String) {
+            RenderWrapper(&self.0[..]).__maud_render_to(context, w);
+        }
+    }
+
+    impl<'a, 'b, T: ?Sized> RenderCollection for RenderWrapper<'a, &'b T>
+    where
+        RenderWrapper<'b, T>: RenderCollection,
+    {
+        fn __maud_render_to(&self, context: Context, w: &mut String) {
+            RenderWrapper(*self.0).__maud_render_to(context, w);
+        }
+    }
+
+    /// Renders the items of an iterator, such as `v.iter().map(..)`.
+    ///
+    /// The iterator is cloned, so that the splice can borrow it like any
+    /// other. This takes `&mut self` so that it has the lowest priority:
+    /// types like `ToUppercase` are both iterators and `Display`, and
+    /// should keep rendering through `Display`.
+    pub trait RenderIterator {
+        fn __maud_render_to(&mut self, context: Context, w: &mut String);
+
+        fn __maud_try_render_to<E>(&mut self, context: Context, w: &mut String) -> Result<(), E> {
+            self.__maud_render_to(context, w);
+            Ok(())
+        }
+    }
+
+    impl<'a, I> RenderIterator for RenderWrapper<'a, I>
+    where
+        I: Iterator + Clone,
+        I::Item: Render,
+    {
+        fn __maud_render_to(&mut self, context: Context, w: &mut String) {
+            let mut items = ItemWriter::new(context, w);
+            for item in self.0.clone() {
+                items.write(&item);
+            }
+            items.finish();
+        }
+    }
+
+    /// Writes the items of a collection in order.
+    ///
+    /// In an attribute value, such as `class` or `data-tags`, the items
+    /// are separated by spaces, and items that render nothing are
+    /// skipped. In element content, they are written with no separator.
+    /// In a URL, they are also written with no separator, and only the
+    /// first item that renders something can set the scheme; the rest
+    /// are escaped as part of the path. In JavaScript code, the items are
+    /// written as an array, such as `['a','b']`.
+    struct ItemWriter<'w> {
+        context: Context,
+        w: &'w mut String,
+        needs_separator: bool,
+    }
+
+    impl<'w> ItemWriter<'w> {
+        fn new(context: Context, w: &'w mut String) -> ItemWriter<'w> {
+            let items = ItemWriter {
+                context,
+                w,
+                needs_separator: false,
+            };
+            if items.is_js_array() {
+                items.w.push('[');
+            }
+            items
+        }
+
+        fn is_js_array(&self) -> bool {
+            self.context.position() == Position::JsValue
+        }
+
+        fn separator(&self) -> Option<char> {
+            match self.context.position() {
+                Position::Attribute => Some(' '),
+                Position::JsValue => Some(','),
+                _ => None,
+            }
+        }
+
+        fn write<T: Render + ?Sized>(&mut self, item: &T) {
+            let start = self.w.len();
+            if let (true, Some(separator)) = (self.needs_separator, self.separator()) {
+                self.w.push(separator);
+            }
+            let item_start = self.w.len();
+            item.render_to_context(self.context, self.w);
+            if self.w.len() == item_start && !self.is_js_array() {
+                self.w.truncate(start);
+            } else {
+                self.needs_separator = true;
+                if self.context.position() == Position::Url {
+                    self.context = self.context.with_position(Position::UrlPath);
+                }
+            }
+        }
+
+        fn finish(self) {
+            if self.is_js_array() {
+                self.w.push(']');
+            }
+        }
+    }
+
+    /// Writes the attributes of a spread, as in `..(attrs)`.
+    ///
+    /// Names that are not valid attribute names are skipped, as are
+    /// names in `known_names` (the attributes written out in the
+    /// template) and names that appeared earlier in the spread. Each
+    /// value is escaped for its attribute, with the profile, charset and
+    /// URL schemes of `context`.
+    pub fn spread_attrs<I, K, V>(attrs: I, context: Context, known_names: &[&str], w: &mut String)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Render,
+    {
+        let mut seen = Vec::new();
+        for (name, value) in attrs {
+            let name = name.as_ref();
+            if !maud_htmlescape::is_valid_attribute_name(name)
+                || (context.charset().is_ascii_only() && !name.is_ascii())
+            {
+                continue;
+            }
+            let lowercase = name.to_ascii_lowercase();
+            if known_names.contains(&lowercase.as_str()) || seen.contains(&lowercase) {
+                continue;
+            }
+            w.push(' ');
+            w.push_str(name);
+            w.push_str("=\"");
+            value.render_to_context(attribute_context(&lowercase, context), w);
+            w.push('"');
+            seen.push(lowercase);
+        }
+    }
+
+    /// Joins the values of an attribute that come from several sources,
+    /// such as the `.foo` shorthands and `class=` values of a `class`
+    /// attribute.
+    ///
+    /// Values are separated by spaces, and values that render nothing
+    /// are skipped.
+    #[derive(Default)]
+    pub struct MergedAttr {
+        value: String,
+        value_start: usize,
+        separator_end: usize,
+    }
+
+    impl MergedAttr {
+        /// Starts the next value, after a space if one is needed.
+        pub fn start_value(&mut self) {
+            self.value_start = self.value.len();
+            if !self.value.is_empty() {
+                self.value.push(' ');
+            }
+            self.separator_end = self.value.len();
+        }
+
+        /// Returns the buffer that the current value is written to.
+        pub fn buffer(&mut self) -> &mut String {
+            &mut self.value
+        }
+
+        /// Ends the current value, removing its space if it was empty.
+        pub fn end_value(&mut self) {
+            if self.value.len() == self.separator_end {
+                self.value.truncate(self.value_start);
+            }
+        }
+
+        /// Writes the attribute, unless every value was empty.
+        ///
+        /// Returns whether the attribute was written.
+        pub fn finish(self, name: &str, w: &mut String) -> bool {
+            if self.value.is_empty() {
+                return false;
+            }
+            w.push(' ');
+            w.push_str(name);
+            w.push_str("=\"");
+            w.push_str(&self.value);
+            w.push('"');
+            true
+        }
+    }
+
+    /// Checks the scheme of a URL attribute value that starts with a
+    /// splice, once the rest of the value has been written.
+    ///
+    /// The splice only checks its own scheme, but the text after it
+    /// could complete one, as in `href={ (scheme) ":alert(1)" }`. If the
+    /// splice, given by `splice`, ends its own scheme or path segment,
+    /// its check already decided the scheme, so it isn't repeated. This
+    /// keeps a leading `TrustedUrl` trusted.
+    pub fn check_url(context: Context, splice: Range<usize>, w: &mut String) {
+        let start = splice.start;
+        if !w[splice].contains([':', '/', '?', '#']) && !context.allows_url(&w[start..]) {
+            w.truncate(start);
+            w.push_str("about:invalid");
+        }
+    }
+
+    /// Checks the name of an `@element`.
+    ///
+    /// Returns `span` instead if the name isn't a valid element name, or
+    /// if it's `script` or `style`, since their contents would be
+    /// escaped as HTML instead of as code. A void element such as `br`
+    /// that is given a body is also replaced, so that the body is kept
+    /// inside the element.
+    pub fn element_name<T: AsRef<str> + ?Sized>(name: &T, has_body: bool) -> &str {
+        let name = name.as_ref();
+        if !maud_htmlescape::is_valid_element_name(name)
+            || name.eq_ignore_ascii_case("script")
+            || name.eq_ignore_ascii_case("style")
+            || (has_body && is_void_element(name))
+        {
+            return "span";
+        }
+        name
+    }
+
+    /// Returns whether the element has no end tag, such as `br`.
+    pub fn is_void_element(name: &str) -> bool {
+        maud_htmlescape::VOID_ELEMENTS
+            .iter()
+            .any(|void| void.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the context for the value of the named attribute.
+    ///
+    /// This matches the choice that `html!` makes for attributes that
+    /// are written out in the template.
+    fn attribute_context(name: &str, context: Context) -> Context {
+        let attribute_context = if maud_htmlescape::URL_ATTRIBUTES.contains(&name) {
+            Context::new(Position::Url)
+        } else if maud_htmlescape::is_script_attribute(name) {
+            Context::new(Position::JsValue).in_attribute()
+        } else if name == "style" {
+            Context::new(Position::Css).in_attribute()
+        } else if maud_htmlescape::TOKEN_LIST_ATTRIBUTES.contains(&name) {
+            Context::new(Position::Attribute).in_token_list()
+        } else {
+            Context::new(Position::Attribute)
+        };
+        let attribute_context = attribute_context
+            .with_profile(context.profile())
+            .with_charset(context.charset());
+        match context.url_schemes() {
+            Some(schemes) => attribute_context.with_url_schemes(schemes),
+            None => attribute_context.with_any_url_scheme(),
         }
     }
 }
 
 /// A wrapper that renders the inner value without escaping.
-#[derive(Debug, Clone, Copy)]
+///
+/// Every `Display` type is escaped when rendered, so `PreEscaped`
+/// implements `Display` through [`.display()`](#method.display)
+/// instead of directly. It also implements `AsRef<str>`, and splices of
+/// it are still written as-is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct PreEscaped<T: AsRef<str>>(pub T);
 
 impl<T: AsRef<str>> Render for PreEscaped<T> {
@@ -121,81 +705,1133 @@ impl<T: AsRef<str>> Render for PreEscaped<T> {
     }
 }
 
-/// A block of markup is a string that does not need to be escaped.
-///
-/// The `html!` macro expands to an expression of this type.
-pub type Markup = PreEscaped<String>;
+impl<T: AsRef<str>> Render for &PreEscaped<T> {
+    fn render_to(&self, w: &mut String) {
+        w.push_str(self.0.as_ref());
+    }
+}
 
-impl<T: AsRef<str> + Into<String>> PreEscaped<T> {
-    /// Converts the inner value to a string.
-    pub fn into_string(self) -> String {
-        self.0.into()
+impl<T: AsRef<str>> PreEscaped<T> {
+    /// Returns the markup as a string slice.
+    pub fn as_str(&self) -> &str {
+        self.0.as_ref()
+    }
+
+    /// Returns a wrapper that implements `Display`, for printing the
+    /// markup with `println!` or `format!`.
+    ///
+    /// ```rust
+    /// use maud::html;
+    ///
+    /// let markup = html! { p { "Hi" } };
+    /// assert_eq!(format!("{}", markup.display()), "<p>Hi</p>");
+    /// ```
+    pub fn display(&self) -> PreEscapedDisplay<'_> {
+        PreEscapedDisplay(self.0.as_ref())
     }
 }
 
-impl<T: AsRef<str> + Into<String>> From<PreEscaped<T>> for String {
-    fn from(value: PreEscaped<T>) -> String {
-        value.into_string()
+impl<T: AsRef<str>> AsRef<str> for PreEscaped<T> {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
     }
 }
 
-pub use maud_htmlescape::Escaper;
+/// Prints `PreEscaped` markup as-is, as returned by
+/// [`PreEscaped::display`](struct.PreEscaped.html#method.display).
+///
+/// Like any other `Display` type, this is escaped when spliced into a
+/// template. Splice the `PreEscaped` value itself instead.
+#[derive(Debug, Clone, Copy)]
+pub struct PreEscapedDisplay<'a>(&'a str);
 
-/// The literal string `<!DOCTYPE html>`.
+impl<'a> fmt::Display for PreEscapedDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl ops::Deref for PreEscaped<String> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// A wrapper that marks a URL as trusted, so that its scheme is not
+/// checked.
+///
+/// By default, a splice at the start of a URL-valued attribute such as
+/// `href` or `src` may only use the schemes in
+/// [`Context::DEFAULT_URL_SCHEMES`](struct.Context.html#associatedconstant.DEFAULT_URL_SCHEMES),
+/// and URLs with any other scheme are replaced with `about:invalid`.
+/// Wrapping the URL in `TrustedUrl` skips this check. The URL is still
+/// escaped as usual.
 ///
 /// # Example
 ///
-/// A minimal web page:
+/// ```rust
+/// use maud::{html, TrustedUrl};
+///
+/// let url = "javascript:history.back()";
+/// let markup = html! {
+///     a href=(url) { "Unchecked" }
+///     a href=(TrustedUrl(url)) { "Trusted" }
+/// };
+/// assert_eq!(markup.into_string(), concat!(
+///     r#"<a href="about:invalid">Unchecked</a>"#,
+///     r#"<a href="javascript:history.back()">Trusted</a>"#,
+/// ));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct TrustedUrl<T: AsRef<str>>(pub T);
+
+impl<T: AsRef<str>> Render for TrustedUrl<T> {
+    fn render_to(&self, w: &mut String) {
+        let _ = Escaper::new(w).write_str(self.0.as_ref());
+    }
+
+    fn render_to_context(&self, context: Context, w: &mut String) {
+        let mut escaper = ContextEscaper::new(context.with_any_url_scheme(), w);
+        let _ = escaper.write_str(self.0.as_ref());
+        escaper.finish();
+    }
+}
+
+/// A `style` attribute value, built up one property at a time.
+///
+/// Each property name and value is escaped with
+/// [`CssEscaper`](struct.CssEscaper.html), so a value can't end the
+/// declaration or add properties of its own.
+///
+/// # Example
 ///
 /// ```rust
-/// use maud::{DOCTYPE, html};
+/// use maud::{html, Style};
 ///
+/// let color = "red; position: fixed";
+/// let bold = true;
 /// let markup = html! {
-///     (DOCTYPE)
-///     html {
-///         head {
-///             meta charset="utf-8";
-///             title { "Test page" }
-///         }
-///         body {
-///             p { "Hello, world!" }
-///         }
-///     }
+///     p style=(Style::new()
+///         .property("color", color)
+///         .property_if(bold, "font-weight", "bold")
+///     ) { "Hi" }
 /// };
+/// assert_eq!(
+///     markup.into_string(),
+///     r#"<p style="color: red\3b  position\3a  fixed; font-weight: bold">Hi</p>"#,
+/// );
 /// ```
-pub const DOCTYPE: PreEscaped<&'static str> = PreEscaped("<!DOCTYPE html>");
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style(String);
 
-#[cfg(feature = "iron")]
-mod iron_support {
-    use crate::PreEscaped;
-    use iron::headers::ContentType;
-    use iron::modifier::{Modifier, Set};
-    use iron::modifiers::Header;
-    use iron::response::{Response, WriteBody};
-    use std::io;
+impl Style {
+    /// Creates an empty `Style`.
+    pub fn new() -> Style {
+        Style(String::new())
+    }
 
-    impl Modifier<Response> for PreEscaped<String> {
-        fn modify(self, response: &mut Response) {
-            response
-                .set_mut(Header(ContentType::html()))
-                .set_mut(Box::new(self) as Box<dyn WriteBody>);
+    /// Adds a property.
+    pub fn property(mut self, name: &str, value: impl fmt::Display) -> Style {
+        if !self.0.is_empty() {
+            self.0.push_str("; ");
         }
+        let _ = CssEscaper::new(&mut self.0).write_str(name);
+        self.0.push_str(": ");
+        let _ = write!(CssEscaper::new(&mut self.0), "{}", value);
+        self
     }
 
-    impl WriteBody for PreEscaped<String> {
-        fn write_body(&mut self, body: &mut dyn io::Write) -> io::Result<()> {
-            self.0.write_body(body)
+    /// Adds a property, but only if `condition` is true.
+    ///
+    /// This works like the `.class[condition]` syntax in `html!`.
+    pub fn property_if(self, condition: bool, name: &str, value: impl fmt::Display) -> Style {
+        if condition {
+            self.property(name, value)
+        } else {
+            self
         }
     }
+
+    /// Returns whether no properties have been added.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the escaped CSS text.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
-#[cfg(feature = "rocket")]
-mod rocket_support {
-    use crate::PreEscaped;
-    use rocket::http::{ContentType, Status};
-    use rocket::request::Request;
-    use rocket::response::{Responder, Response};
-    use std::io::Cursor;
+impl Render for Style {
+    fn render_to(&self, w: &mut String) {
+        // The CSS escaper never outputs HTML special characters
+        w.push_str(&self.0);
+    }
+}
+
+/// A `class` attribute value, built up one class at a time.
+///
+/// Classes are separated by spaces, and a class that was already
+/// added, or is empty, is skipped. The result is escaped when spliced,
+/// like any other string.
+///
+/// # Example
+///
+/// ```rust
+/// use maud::{html, Classes};
+///
+/// let (active, size) = (true, "large");
+/// let classes = Classes::new()
+///     .class("button")
+///     .class_if(active, "active")
+///     .class(format!("button-{}", size));
+/// let markup = html! {
+///     a.nav-link class=(classes) href="/" { "Home" }
+/// };
+/// assert_eq!(
+///     markup.into_string(),
+///     r#"<a class="nav-link button active button-large" href="/">Home</a>"#,
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Classes(String);
+
+impl Classes {
+    /// Creates an empty `Classes`.
+    pub fn new() -> Classes {
+        Classes(String::new())
+    }
+
+    /// Adds a class.
+    ///
+    /// If `name` contains spaces, each part is added as its own class.
+    pub fn class(mut self, name: impl AsRef<str>) -> Classes {
+        for name in name.as_ref().split_ascii_whitespace() {
+            if !self.0.split(' ').any(|class| class == name) {
+                if !self.0.is_empty() {
+                    self.0.push(' ');
+                }
+                self.0.push_str(name);
+            }
+        }
+        self
+    }
+
+    /// Adds a class, but only if `condition` is true.
+    ///
+    /// This works like the `.class[condition]` syntax in `html!`.
+    pub fn class_if(self, condition: bool, name: impl AsRef<str>) -> Classes {
+        if condition {
+            self.class(name)
+        } else {
+            self
+        }
+    }
+
+    /// Returns whether no classes have been added.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the classes, separated by spaces.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Classes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for Classes {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Classes {
+        iter.into_iter().fold(Classes::new(), Classes::class)
+    }
+}
+
+impl<S: AsRef<str>> Extend<S> for Classes {
+    fn extend<I: IntoIterator<Item = S>>(&mut self, iter: I) {
+        let classes = std::mem::take(self);
+        *self = iter.into_iter().fold(classes, Classes::class);
+    }
+}
+
+/// A block of markup is a string that does not need to be escaped.
+///
+/// The `html!` macro expands to an expression of this type.
+pub type Markup = PreEscaped<String>;
+
+impl<T: AsRef<str> + Into<String>> PreEscaped<T> {
+    /// Converts the inner value to a string.
+    pub fn into_string(self) -> String {
+        self.0.into()
+    }
+}
+
+impl<T: AsRef<str> + Into<String>> From<PreEscaped<T>> for String {
+    fn from(value: PreEscaped<T>) -> String {
+        value.into_string()
+    }
+}
+
+/// A block of markup that may be borrowed.
+///
+/// This can hold the `&'static str` from
+/// [`html_static!`](macro.html_static.html) or [`DOCTYPE`] without
+/// copying it, or the `String` from `html!`.
+///
+/// # Example
+///
+/// ```rust
+/// use maud::{html, html_static, CowMarkup};
+///
+/// fn footer(year: Option<u32>) -> CowMarkup<'static> {
+///     match year {
+///         Some(year) => html! { footer { "© " (year) } }.into(),
+///         None => html_static! { footer { "© Ferris" } }.into(),
+///     }
+/// }
+/// ```
+pub type CowMarkup<'a> = PreEscaped<Cow<'a, str>>;
+
+impl<'a> From<PreEscaped<String>> for CowMarkup<'a> {
+    fn from(markup: PreEscaped<String>) -> CowMarkup<'a> {
+        PreEscaped(Cow::Owned(markup.0))
+    }
+}
+
+impl<'a> From<PreEscaped<&'a str>> for CowMarkup<'a> {
+    fn from(markup: PreEscaped<&'a str>) -> CowMarkup<'a> {
+        PreEscaped(Cow::Borrowed(markup.0))
+    }
+}
+
+impl<T: AsRef<str>> ops::Add<PreEscaped<T>> for Markup {
+    type Output = Markup;
+
+    fn add(mut self, other: PreEscaped<T>) -> Markup {
+        self += other;
+        self
+    }
+}
+
+impl<T: AsRef<str>> ops::AddAssign<PreEscaped<T>> for Markup {
+    fn add_assign(&mut self, other: PreEscaped<T>) {
+        self.0.push_str(other.0.as_ref());
+    }
+}
+
+impl<T: AsRef<str>> Extend<PreEscaped<T>> for Markup {
+    fn extend<I: IntoIterator<Item = PreEscaped<T>>>(&mut self, iter: I) {
+        for markup in iter {
+            *self += markup;
+        }
+    }
+}
+
+impl<T: AsRef<str>> FromIterator<PreEscaped<T>> for Markup {
+    fn from_iter<I: IntoIterator<Item = PreEscaped<T>>>(iter: I) -> Markup {
+        let mut result = PreEscaped(String::new());
+        result.extend(iter);
+        result
+    }
+}
+
+/// Renders each item in turn, with `separator` rendered between them.
+///
+/// Both the items and the separator are rendered with [`Render`], so
+/// text is escaped and markup is not.
+///
+/// # Example
+///
+/// ```rust
+/// use maud::{html, join};
+///
+/// let tags = ["rust", "<html>"];
+/// let links = join(
+///     tags.iter().map(|tag| html! { a href={ "/tags/" (tag) } { (tag) } }),
+///     ", ",
+/// );
+/// assert_eq!(
+///     links.into_string(),
+///     r#"<a href="/tags/rust">rust</a>, <a href="/tags/%3Chtml%3E">&lt;html&gt;</a>"#,
+/// );
+/// ```
+pub fn join<I>(items: I, separator: impl Render) -> Markup
+where
+    I: IntoIterator,
+    I::Item: Render,
+{
+    let mut buffer = String::new();
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            separator.render_to(&mut buffer);
+        }
+        item.render_to(&mut buffer);
+    }
+    PreEscaped(buffer)
+}
+
+/// A sink that the [`html_to!`](macro.html_to.html) macro writes
+/// markup into.
+///
+/// Markup is appended to the string returned by `.buffer()`. A `String`
+/// is its own buffer, so writing into one works just like `html!`. Other
+/// sinks, such as [`FmtOutput`] and [`IoOutput`], keep a small buffer
+/// and pass it on to the underlying writer whenever it fills up, so that
+/// the full document is never held in memory.
+///
+/// # Example
+///
+/// ```rust
+/// use maud::{html_to, IoOutput};
+///
+/// let mut file = Vec::new();
+/// html_to!(IoOutput::new(&mut file), {
+///     h1 { "Hello, world!" }
+/// }).unwrap();
+/// assert_eq!(file, b"<h1>Hello, world!</h1>");
+/// ```
+pub trait Output {
+    /// The type of error that writing can return.
+    type Error;
+
+    /// Returns the buffer to append markup to.
+    ///
+    /// This is called before every write, so an implementation may use
+    /// it to pass on the contents of a full buffer.
+    fn buffer(&mut self) -> &mut String;
+
+    /// Passes on any markup left in the buffer, and returns the first
+    /// error that happened while writing.
+    fn finish(&mut self) -> Result<(), Self::Error>;
+}
+
+impl Output for String {
+    type Error = std::convert::Infallible;
+
+    fn buffer(&mut self) -> &mut String {
+        self
+    }
+
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<O: Output + ?Sized> Output for &mut O {
+    type Error = O::Error;
+
+    fn buffer(&mut self) -> &mut String {
+        (**self).buffer()
+    }
+
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        (**self).finish()
+    }
+}
+
+/// The number of bytes that [`FmtOutput`] and [`IoOutput`] buffer by
+/// default.
+const DEFAULT_OUTPUT_CAPACITY: usize = 8 * 1024;
+
+/// An [`Output`] that writes into a [`fmt::Write`].
+///
+/// This lets a template write straight into a `fmt::Formatter`, for
+/// example in a `Display` impl.
+#[derive(Debug)]
+pub struct FmtOutput<W: fmt::Write> {
+    inner: W,
+    buffer: String,
+    capacity: usize,
+    error: Option<fmt::Error>,
+}
+
+impl<W: fmt::Write> FmtOutput<W> {
+    /// Creates a new `FmtOutput` with the default buffer size.
+    pub fn new(inner: W) -> FmtOutput<W> {
+        FmtOutput::with_capacity(inner, DEFAULT_OUTPUT_CAPACITY)
+    }
+
+    /// Creates a new `FmtOutput` that passes on its buffer once it holds
+    /// at least `capacity` bytes.
+    pub fn with_capacity(inner: W, capacity: usize) -> FmtOutput<W> {
+        FmtOutput {
+            inner,
+            buffer: String::with_capacity(capacity),
+            capacity,
+            error: None,
+        }
+    }
+
+    /// Returns the underlying writer.
+    ///
+    /// Any markup still in the buffer is discarded, so call `.finish()`
+    /// first.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn write_buffer(&mut self) {
+        if self.error.is_none() {
+            if let Err(error) = self.inner.write_str(&self.buffer) {
+                self.error = Some(error);
+            }
+        }
+        self.buffer.clear();
+    }
+}
+
+impl<W: fmt::Write> Output for FmtOutput<W> {
+    type Error = fmt::Error;
+
+    fn buffer(&mut self) -> &mut String {
+        if self.buffer.len() >= self.capacity {
+            self.write_buffer();
+        }
+        &mut self.buffer
+    }
+
+    fn finish(&mut self) -> Result<(), fmt::Error> {
+        self.write_buffer();
+        self.error.take().map_or(Ok(()), Err)
+    }
+}
+
+/// An [`Output`] that writes into an [`io::Write`](std::io::Write), such
+/// as a file or a socket.
+///
+/// Once a write fails, nothing more is written, and `.finish()` returns
+/// the error. The underlying writer is not flushed.
+#[derive(Debug)]
+pub struct IoOutput<W: io::Write> {
+    inner: W,
+    buffer: String,
+    capacity: usize,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> IoOutput<W> {
+    /// Creates a new `IoOutput` with the default buffer size.
+    pub fn new(inner: W) -> IoOutput<W> {
+        IoOutput::with_capacity(inner, DEFAULT_OUTPUT_CAPACITY)
+    }
+
+    /// Creates a new `IoOutput` that passes on its buffer once it holds
+    /// at least `capacity` bytes.
+    pub fn with_capacity(inner: W, capacity: usize) -> IoOutput<W> {
+        IoOutput {
+            inner,
+            buffer: String::with_capacity(capacity),
+            capacity,
+            error: None,
+        }
+    }
+
+    /// Returns the underlying writer.
+    ///
+    /// Any markup still in the buffer is discarded, so call `.finish()`
+    /// first.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn write_buffer(&mut self) {
+        if self.error.is_none() {
+            if let Err(error) = self.inner.write_all(self.buffer.as_bytes()) {
+                self.error = Some(error);
+            }
+        }
+        self.buffer.clear();
+    }
+}
+
+impl<W: io::Write> Output for IoOutput<W> {
+    type Error = io::Error;
+
+    fn buffer(&mut self) -> &mut String {
+        if self.buffer.len() >= self.capacity {
+            self.write_buffer();
+        }
+        &mut self.buffer
+    }
+
+    fn finish(&mut self) -> Result<(), io::Error> {
+        self.write_buffer();
+        self.error.take().map_or(Ok(()), Err)
+    }
+}
+
+pub use maud_htmlescape::{
+    Charset, Context, ContextEscaper, CssEscaper, Escaper, FmtEscaper, InvalidChars, IoEscaper,
+    Position, Profile, VecEscaper,
+};
+
+/// The literal string `<!DOCTYPE html>`.
+///
+/// # Example
+///
+/// A minimal web page:
+///
+/// ```rust
+/// use maud::{DOCTYPE, html};
+///
+/// let markup = html! {
+///     (DOCTYPE)
+///     html {
+///         head {
+///             meta charset="utf-8";
+///             title { "Test page" }
+///         }
+///         body {
+///             p { "Hello, world!" }
+///         }
+///     }
+/// };
+/// ```
+pub const DOCTYPE: PreEscaped<&'static str> = PreEscaped("<!DOCTYPE html>");
+
+#[cfg(feature = "serde")]
+pub use crate::json_support::Json;
+
+#[cfg(feature = "serde")]
+mod json_support {
+    use crate::Render;
+    use maud_htmlescape::{Context, ContextEscaper, Escaper, Position};
+    use serde_dep::Serialize;
+    use std::fmt::Write;
+
+    /// A wrapper that renders the inner value as JSON.
+    ///
+    /// The characters `<`, `>` and `&` are written as `\u003c`, `\u003e`
+    /// and `\u0026`, so that the JSON can't end a `script` element or
+    /// start an HTML comment. The line terminators `U+2028` and
+    /// `U+2029` are escaped too, since older JavaScript engines don't
+    /// allow them in string literals. The result is still valid JSON.
+    ///
+    /// This makes it safe to use in a `script` element, whether it is
+    /// data (`type="application/json"` or `"application/ld+json"`) or
+    /// an initializer such as `var state = (Json(state));`. In other
+    /// places, such as attribute values, the JSON is HTML-escaped as
+    /// well.
+    ///
+    /// If the value can't be serialized, this renders `null` instead.
+    ///
+    /// This type is only available with the `serde` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use maud::{html, Json};
+    ///
+    /// let comment = "</script><script>alert(1)</script>";
+    /// let markup = html! {
+    ///     script type="application/json" { (Json(&[comment])) }
+    /// };
+    /// assert_eq!(
+    ///     markup.into_string(),
+    ///     r#"<script type="application/json">["\u003c/script\u003e\u003cscript\u003ealert(1)\u003c/script\u003e"]</script>"#,
+    /// );
+    /// ```
+    #[derive(Debug, Clone, Copy)]
+    pub struct Json<T: Serialize>(pub T);
+
+    impl<T: Serialize> Json<T> {
+        /// Serializes the value, with HTML special characters escaped.
+        fn to_safe_json(&self) -> String {
+            let json = serde_json::to_string(&self.0).unwrap_or_else(|_| "null".to_owned());
+            let mut result = String::with_capacity(json.len());
+            for c in json.chars() {
+                match c {
+                    '<' => result.push_str("\\u003c"),
+                    '>' => result.push_str("\\u003e"),
+                    '&' => result.push_str("\\u0026"),
+                    '\u{2028}' => result.push_str("\\u2028"),
+                    '\u{2029}' => result.push_str("\\u2029"),
+                    c => result.push(c),
+                }
+            }
+            result
+        }
+    }
+
+    impl<T: Serialize> Render for Json<T> {
+        fn render_to(&self, w: &mut String) {
+            w.push_str(&self.to_safe_json());
+        }
+
+        fn render_to_context(&self, context: Context, w: &mut String) {
+            let json = self.to_safe_json();
+            match context.position() {
+                Position::Html => w.push_str(&json),
+                Position::JsValue if !context.is_attribute() => w.push_str(&json),
+                Position::Attribute | Position::JsValue => {
+                    let _ = Escaper::with_profile(w, context.profile()).write_str(&json);
+                }
+                _ => {
+                    let mut escaper = ContextEscaper::new(context, w);
+                    let _ = escaper.write_str(&json);
+                    escaper.finish();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_support {
+    use crate::PreEscaped;
+    use serde_dep::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serializes the markup as a string.
+    impl<T: AsRef<str>> Serialize for PreEscaped<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(self.0.as_ref())
+        }
+    }
+
+    /// Deserializes markup from a string.
+    ///
+    /// The string is trusted as-is, so only deserialize markup that was
+    /// serialized by your own code, such as a cache entry.
+    impl<'de, T: AsRef<str> + Deserialize<'de>> Deserialize<'de> for PreEscaped<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PreEscaped<T>, D::Error> {
+            T::deserialize(deserializer).map(PreEscaped)
+        }
+    }
+}
+
+#[cfg(feature = "ammonia")]
+pub use crate::sanitize_support::{Policy, Sanitized};
+
+#[cfg(feature = "ammonia")]
+mod sanitize_support {
+    use crate::{Markup, PreEscaped, Render};
+    use maud_htmlescape::{Context, ContextEscaper, Position};
+    use std::fmt::Write;
+
+    /// HTML that has been cleaned of anything not on an allowlist.
+    ///
+    /// Use this to render HTML from an untrusted source, such as a user
+    /// comment. Tags, attributes and URL schemes that the [`Policy`]
+    /// doesn't allow are removed, along with comments and the contents
+    /// of `script` and `style` elements.
+    ///
+    /// This type is only available with the `ammonia` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use maud::{html, Policy, Sanitized};
+    ///
+    /// let comment = r#"<b onclick="steal()">Hi</b><script>steal()</script>"#;
+    /// let markup = html! {
+    ///     div { (Sanitized::with_policy(comment, &Policy::comments())) }
+    /// };
+    /// assert_eq!(markup.into_string(), "<div><b>Hi</b></div>");
+    /// ```
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Sanitized(String);
+
+    impl Sanitized {
+        /// Cleans the given HTML with the [`Policy::rich_text`] policy.
+        pub fn new(html: &str) -> Sanitized {
+            Sanitized(ammonia::clean(html))
+        }
+
+        /// Cleans the given HTML with the given policy.
+        pub fn with_policy(html: &str, policy: &Policy) -> Sanitized {
+            Sanitized(policy.0.clean(html).to_string())
+        }
+
+        /// Returns the cleaned HTML.
+        pub fn as_str(&self) -> &str {
+            &self.0
+        }
+
+        /// Converts the cleaned HTML to `Markup`.
+        pub fn into_markup(self) -> Markup {
+            PreEscaped(self.0)
+        }
+    }
+
+    impl Render for Sanitized {
+        fn render_to(&self, w: &mut String) {
+            w.push_str(&self.0);
+        }
+
+        fn render_to_context(&self, context: Context, w: &mut String) {
+            if context.position() == Position::Html {
+                w.push_str(&self.0);
+            } else {
+                // Outside of element content, the markup is only text
+                let mut escaper = ContextEscaper::new(context, w);
+                let _ = escaper.write_str(&self.0);
+                escaper.finish();
+            }
+        }
+    }
+
+    /// The tags, attributes and URL schemes that [`Sanitized`] allows.
+    ///
+    /// Start with one of the presets, and adjust it if needed:
+    ///
+    /// * [`Policy::comments`] allows basic inline formatting, links
+    ///   and lists.
+    /// * [`Policy::markdown`] allows everything that a Markdown
+    ///   renderer usually outputs, including images and tables.
+    /// * [`Policy::rich_text`] allows most formatting tags, as used by
+    ///   rich text editors. This is also the [`Default`].
+    ///
+    /// For anything not covered here, configure the underlying
+    /// [`ammonia::Builder`] with [`Policy::builder_mut`].
+    ///
+    /// Building a policy is relatively expensive, so build it once and
+    /// reuse it for every call to [`Sanitized::with_policy`].
+    #[derive(Debug)]
+    pub struct Policy(ammonia::Builder<'static>);
+
+    impl Policy {
+        /// Allows basic inline formatting, links and lists.
+        ///
+        /// Links are given `rel="nofollow noopener noreferrer"`.
+        pub fn comments() -> Policy {
+            let mut builder = ammonia::Builder::empty();
+            builder
+                .add_tags(&[
+                    "a",
+                    "b",
+                    "blockquote",
+                    "br",
+                    "code",
+                    "em",
+                    "i",
+                    "li",
+                    "ol",
+                    "p",
+                    "pre",
+                    "strong",
+                    "ul",
+                ])
+                .add_tag_attributes("a", &["href"])
+                .add_url_schemes(&["http", "https", "mailto"])
+                .link_rel(Some("nofollow noopener noreferrer"));
+            Policy(builder)
+        }
+
+        /// Allows the output of a typical Markdown renderer.
+        pub fn markdown() -> Policy {
+            let mut builder = ammonia::Builder::empty();
+            builder
+                .add_tags(&[
+                    "a",
+                    "blockquote",
+                    "br",
+                    "code",
+                    "del",
+                    "em",
+                    "h1",
+                    "h2",
+                    "h3",
+                    "h4",
+                    "h5",
+                    "h6",
+                    "hr",
+                    "img",
+                    "li",
+                    "ol",
+                    "p",
+                    "pre",
+                    "strong",
+                    "sup",
+                    "table",
+                    "tbody",
+                    "td",
+                    "th",
+                    "thead",
+                    "tr",
+                    "ul",
+                ])
+                .add_tag_attributes("a", &["href", "title"])
+                .add_tag_attributes("img", &["src", "alt", "title"])
+                .add_tag_attributes("ol", &["start"])
+                .add_url_schemes(&["http", "https", "mailto"]);
+            Policy(builder)
+        }
+
+        /// Allows most formatting tags, as used by rich text editors.
+        ///
+        /// This is the default policy of the `ammonia` crate.
+        pub fn rich_text() -> Policy {
+            Policy(ammonia::Builder::default())
+        }
+
+        /// Allows the given tags, in addition to the existing ones.
+        pub fn add_tags(mut self, tags: &[&'static str]) -> Policy {
+            self.0.add_tags(tags.iter().copied());
+            self
+        }
+
+        /// Stops allowing the given tags.
+        pub fn rm_tags(mut self, tags: &[&'static str]) -> Policy {
+            self.0.rm_tags(tags);
+            self
+        }
+
+        /// Allows the given attributes on the given tag.
+        pub fn add_tag_attributes(mut self, tag: &'static str, attrs: &[&'static str]) -> Policy {
+            self.0.add_tag_attributes(tag, attrs.iter().copied());
+            self
+        }
+
+        /// Allows the given attributes on all tags.
+        pub fn add_generic_attributes(mut self, attrs: &[&'static str]) -> Policy {
+            self.0.add_generic_attributes(attrs.iter().copied());
+            self
+        }
+
+        /// Allows only the given URL schemes.
+        ///
+        /// Relative URLs are always allowed.
+        pub fn url_schemes(mut self, schemes: &[&'static str]) -> Policy {
+            self.0.url_schemes(schemes.iter().copied().collect());
+            self
+        }
+
+        /// Returns the underlying `ammonia::Builder`, for more advanced
+        /// configuration.
+        pub fn builder_mut(&mut self) -> &mut ammonia::Builder<'static> {
+            &mut self.0
+        }
+    }
+
+    impl Default for Policy {
+        fn default() -> Policy {
+            Policy::rich_text()
+        }
+    }
+
+    impl From<ammonia::Builder<'static>> for Policy {
+        fn from(builder: ammonia::Builder<'static>) -> Policy {
+            Policy(builder)
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+pub use crate::stream_support::{MarkupStream, StreamOutput};
+#[cfg(feature = "stream")]
+pub use maud_macros::html_stream;
+
+#[cfg(feature = "stream")]
+mod stream_support {
+    use crate::Output;
+    use futures_core::Stream;
+    use std::future::Future;
+    use std::mem;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+
+    /// The number of bytes that [`MarkupStream`] buffers by default.
+    const DEFAULT_CHUNK_SIZE: usize = 8 * 1024;
+
+    /// A stream of markup, as returned by the
+    /// [`html_stream!`](macro.html_stream.html) macro.
+    ///
+    /// Each item is a chunk of the page. The template runs only as the
+    /// stream is polled. After every splice, every `@for` or `@while`
+    /// iteration, and every long run of literal markup, the markup so far
+    /// is sent as a chunk once it reaches the chunk size. This way a web
+    /// server can start sending a large page before all of it has been
+    /// generated.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use maud::html_stream;
+    ///
+    /// let rows = vec!["one", "two", "three"];
+    /// let stream = html_stream! {
+    ///     table {
+    ///         @for row in &rows {
+    ///             tr { td { (row) } }
+    ///         }
+    ///     }
+    /// }
+    /// .chunk_size(16);
+    /// ```
+    pub struct MarkupStream<F> {
+        future: Option<Pin<Box<F>>>,
+        shared: Arc<Mutex<Shared>>,
+    }
+
+    /// State shared between a [`MarkupStream`] and its [`StreamOutput`].
+    #[derive(Debug)]
+    struct Shared {
+        chunk: String,
+        chunk_size: usize,
+    }
+
+    impl<F: Future<Output = ()>> MarkupStream<F> {
+        #[doc(hidden)]
+        pub fn __maud_new() -> (MarkupStream<F>, StreamOutput) {
+            let shared = Arc::new(Mutex::new(Shared {
+                chunk: String::new(),
+                chunk_size: DEFAULT_CHUNK_SIZE,
+            }));
+            let output = StreamOutput {
+                buffer: String::new(),
+                shared: Arc::clone(&shared),
+            };
+            let stream = MarkupStream {
+                future: None,
+                shared,
+            };
+            (stream, output)
+        }
+
+        #[doc(hidden)]
+        pub fn __maud_start(mut self, template: F) -> MarkupStream<F> {
+            self.future = Some(Box::pin(template));
+            self
+        }
+
+        /// Sets the number of bytes to buffer before sending a chunk.
+        ///
+        /// With a chunk size of zero, a chunk is sent at every point
+        /// where the stream checks for one.
+        pub fn chunk_size(self, chunk_size: usize) -> MarkupStream<F> {
+            self.shared.lock().unwrap().chunk_size = chunk_size;
+            self
+        }
+    }
+
+    impl<F: Future<Output = ()>> Stream for MarkupStream<F> {
+        type Item = String;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<String>> {
+            let poll = match &mut self.future {
+                Some(future) => future.as_mut().poll(cx),
+                None => return Poll::Ready(None),
+            };
+            if poll.is_ready() {
+                self.future = None;
+            }
+            let chunk = mem::take(&mut self.shared.lock().unwrap().chunk);
+            if !chunk.is_empty() {
+                Poll::Ready(Some(chunk))
+            } else if poll.is_ready() {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    impl<F> std::fmt::Debug for MarkupStream<F> {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.debug_struct("MarkupStream")
+                .field("done", &self.future.is_none())
+                .field("shared", &self.shared)
+                .finish()
+        }
+    }
+
+    /// The [`Output`] that [`html_stream!`](macro.html_stream.html)
+    /// writes into.
+    #[derive(Debug)]
+    pub struct StreamOutput {
+        buffer: String,
+        shared: Arc<Mutex<Shared>>,
+    }
+
+    impl StreamOutput {
+        #[doc(hidden)]
+        pub fn __maud_flush(&mut self) -> impl Future<Output = ()> {
+            let mut shared = self.shared.lock().unwrap();
+            let full = !self.buffer.is_empty() && self.buffer.len() >= shared.chunk_size;
+            if full {
+                shared.chunk = mem::take(&mut self.buffer);
+            }
+            Pause(full)
+        }
+    }
+
+    impl Output for StreamOutput {
+        type Error = std::convert::Infallible;
+
+        fn buffer(&mut self) -> &mut String {
+            &mut self.buffer
+        }
+
+        fn finish(&mut self) -> Result<(), Self::Error> {
+            self.shared
+                .lock()
+                .unwrap()
+                .chunk
+                .push_str(&mem::take(&mut self.buffer));
+            Ok(())
+        }
+    }
+
+    /// A future that returns `Pending` once, so that [`MarkupStream`]
+    /// can send the chunk that was just written.
+    ///
+    /// It wakes the task before returning `Pending`, so that the
+    /// template is polled again even if it runs inside another future.
+    struct Pause(bool);
+
+    impl Future for Pause {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+            if mem::replace(&mut self.0, false) {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "iron")]
+mod iron_support {
+    use crate::PreEscaped;
+    use iron::headers::ContentType;
+    use iron::modifier::{Modifier, Set};
+    use iron::modifiers::Header;
+    use iron::response::{Response, WriteBody};
+    use std::io;
+
+    impl Modifier<Response> for PreEscaped<String> {
+        fn modify(self, response: &mut Response) {
+            response
+                .set_mut(Header(ContentType::html()))
+                .set_mut(Box::new(self) as Box<dyn WriteBody>);
+        }
+    }
+
+    impl WriteBody for PreEscaped<String> {
+        fn write_body(&mut self, body: &mut dyn io::Write) -> io::Result<()> {
+            self.0.write_body(body)
+        }
+    }
+}
+
+#[cfg(feature = "rocket")]
+mod rocket_support {
+    use crate::PreEscaped;
+    use rocket::http::{ContentType, Status};
+    use rocket::request::Request;
+    use rocket::response::{Responder, Response};
+    use std::io::Cursor;
 
     impl Responder<'static> for PreEscaped<String> {
         fn respond_to(self, _: &Request) -> Result<Response<'static>, Status> {
@@ -222,4 +1858,16 @@ mod actix_support {
                 .body(self.0))
         }
     }
+    #[cfg(feature = "stream")]
+    impl<F: std::future::Future<Output = ()> + 'static> Responder for crate::MarkupStream<F> {
+        type Error = Error;
+        type Future = Ready<Result<HttpResponse, Self::Error>>;
+        fn respond_to(self, _req: &HttpRequest) -> Self::Future {
+            use actix_web_dep::web::Bytes;
+            use futures_util::stream::StreamExt;
+            ok(HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .streaming(self.map(|chunk| Ok::<_, Error>(Bytes::from(chunk)))))
+        }
+    }
 }
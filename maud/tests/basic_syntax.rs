@@ -1,4 +1,5 @@
 use maud::{html, Markup};
+use std::collections::BTreeMap;
 
 #[test]
 fn literals() {
@@ -246,6 +247,62 @@ fn mixed_classes() {
     );
 }
 
+#[test]
+fn classes_merged_with_attribute() {
+    fn test(size: &str, is_active: bool, extra: Option<&str>) -> Markup {
+        html! {
+            button.btn.active[is_active] class={ "btn-" (size) } class=[extra] { "Go" }
+        }
+    }
+    assert_eq!(
+        test("lg", true, Some("wide")).into_string(),
+        r#"<button class="btn btn-lg active wide">Go</button>"#
+    );
+    assert_eq!(
+        test("sm", false, None).into_string(),
+        r#"<button class="btn btn-sm">Go</button>"#
+    );
+}
+
+#[test]
+fn classes_merged_skip_empty_values() {
+    let (empty, none) = ("", None::<&str>);
+    let result = html! {
+        p.a class=(empty) {}
+        p class=[none] class=[none] {}
+        p.a[false] class=(empty) class=[Some("b")] {}
+        p.a class="" {}
+        p class="" class="" {}
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<p class="a"></p><p></p><p class="b"></p><p class="a"></p><p></p>"#
+    );
+}
+
+#[test]
+fn classes_merged_with_spread() {
+    let extra = vec![("class", "spread")];
+    let result = html! { p.a[false] class=("") ..(extra) {} };
+    assert_eq!(result.into_string(), r#"<p class="spread"></p>"#);
+}
+
+#[test]
+fn class_attribute_keeps_position() {
+    let result = html! { input type="text" class=("x") name="n"; };
+    assert_eq!(
+        result.into_string(),
+        r#"<input type="text" class="x" name="n">"#
+    );
+}
+
+#[test]
+fn class_attribute_optional() {
+    let missing: Option<&str> = None;
+    let result = html! { p class=[missing] {} p class=[Some("x")] {} };
+    assert_eq!(result.into_string(), r#"<p></p><p class="x"></p>"#);
+}
+
 #[test]
 fn id_shorthand() {
     let result = html! { p { "Hi, " span#thing { "Lyra" } "!" } };
@@ -302,3 +359,180 @@ fn div_shorthand_id_with_attrs() {
         r#"<div class="awesome-class" id="unique-id" contenteditable dir="rtl"></div>"#
     );
 }
+
+#[test]
+fn attribute_spread() {
+    let extra = vec![("data-id", "42"), ("aria-label", "Search \"all\"")];
+    let result = html! { input type="search" ..(extra); };
+    assert_eq!(
+        result.into_string(),
+        r#"<input type="search" data-id="42" aria-label="Search &quot;all&quot;">"#
+    );
+}
+
+#[test]
+fn attribute_spread_map() {
+    let mut data = BTreeMap::new();
+    data.insert("data-a".to_string(), 1);
+    data.insert("data-b".to_string(), 2);
+    let result = html! { .card ..(&data) {} };
+    assert_eq!(
+        result.into_string(),
+        r#"<div class="card" data-a="1" data-b="2"></div>"#
+    );
+}
+
+#[test]
+fn attribute_spread_conflicts() {
+    let extra = vec![
+        ("href", "/elsewhere"),
+        ("class", "sneaky"),
+        ("bad name", "x"),
+        ("\"><script>", "x"),
+        ("data-&amp", "x"),
+        ("title", "first"),
+        ("TITLE", "second"),
+    ];
+    let result = html! { a.link href="/" ..(extra) { "Home" } };
+    assert_eq!(
+        result.into_string(),
+        r#"<a class="link" href="/" title="first">Home</a>"#
+    );
+}
+
+#[test]
+fn attribute_spread_conditional_conflicts() {
+    let extra = vec![("checked", "yes"), ("title", "spread")];
+    let result = html! { input checked[false] title=[None::<&str>] ..(extra); };
+    assert_eq!(
+        result.into_string(),
+        r#"<input checked="yes" title="spread">"#
+    );
+    let extra = vec![("checked", "yes"), ("title", "spread")];
+    let result = html! { input checked[true] title=[Some("kept")] ..(extra); };
+    assert_eq!(result.into_string(), r#"<input checked title="kept">"#);
+}
+
+#[test]
+fn attribute_spread_escaping() {
+    let extra = vec![
+        ("src", "javascript:alert(1)"),
+        ("onclick", "alert(1)"),
+        ("style", "color: red; }"),
+    ];
+    let result = html! { img ..(extra); };
+    assert_eq!(
+        result.into_string(),
+        r#"<img src="about:invalid" onclick="'alert(1)'" style="color\3a  red\3b  \7d ">"#
+    );
+}
+
+#[test]
+fn dynamic_element() {
+    fn heading(level: u8, text: &str) -> Markup {
+        html! {
+            @element(format!("h{}", level)) .title { (text) }
+        }
+    }
+    assert_eq!(
+        heading(1, "Top").into_string(),
+        r#"<h1 class="title">Top</h1>"#
+    );
+    assert_eq!(
+        heading(3, "Nested").into_string(),
+        r#"<h3 class="title">Nested</h3>"#
+    );
+}
+
+#[test]
+fn dynamic_element_nested() {
+    let (outer, inner) = ("section", "article");
+    let result = html! {
+        @element(outer) id="a" {
+            @element(inner) { "Hi" }
+        }
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<section id="a"><article>Hi</article></section>"#
+    );
+}
+
+#[test]
+fn dynamic_element_void() {
+    let result = html! {
+        @element("br");
+        @element("IMG") src="/a.png" {}
+        @element("div");
+    };
+    assert_eq!(result.into_string(), r#"<br><IMG src="/a.png"><div></div>"#);
+}
+
+#[test]
+fn dynamic_element_void_with_body() {
+    let tag = "br";
+    let result = html! { @element(tag) .a { "Hi" } };
+    assert_eq!(result.into_string(), r#"<span class="a">Hi</span>"#);
+}
+
+#[test]
+fn dynamic_element_invalid() {
+    let (tag, empty) = ("div><script", String::new());
+    let result = html! { @element(tag) {} @element(empty) id="b"; };
+    assert_eq!(result.into_string(), r#"<span></span><span id="b"></span>"#);
+}
+
+#[test]
+fn dynamic_element_script() {
+    let (script, style) = ("script", "STYLE");
+    let result = html! {
+        @element(script) { "alert(1)" }
+        @element(style) { "</style>" }
+    };
+    assert_eq!(
+        result.into_string(),
+        "<span>alert(1)</span><span>&lt;/style&gt;</span>"
+    );
+}
+
+#[test]
+fn quoted_attribute_names() {
+    let result = html! {
+        button "@click"="open = true" ":class"="{ active: open }" { "Open" }
+        form "x-on:submit.prevent"="save" "hx-on::after-request"="reset()" {}
+        td "data-2col"="yes" "x-cloak"[true] "x-ignore"[false] {}
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r#"<button @click="open = true" :class="{ active: open }">Open</button>"#,
+            r#"<form x-on:submit.prevent="save" hx-on::after-request="reset()"></form>"#,
+            r#"<td data-2col="yes" x-cloak></td>"#,
+        )
+    );
+}
+
+#[test]
+fn quoted_attribute_names_escaping() {
+    let name = "'); alert(1); ('";
+    let result = html! {
+        button "@click"={ "greet(" (name) ")" } "x-text"=(name) {}
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<button @click="greet('\u0027); alert(1); (\u0027')" x-text="'\u0027); alert(1); (\u0027'"></button>"#
+    );
+}
+
+#[test]
+fn quoted_element_names() {
+    let result = html! {
+        @element("x-card") "x-data"="{}" { "Card" }
+        @element("script") { "let a = 1 < 2;" }
+        @element("br");
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<x-card x-data="{}">Card</x-card><script>let a = 1 < 2;</script><br>"#
+    );
+}
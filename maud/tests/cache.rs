@@ -0,0 +1,98 @@
+use maud::cache::Cache;
+use maud::html;
+use std::cell::Cell;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn renders_once() {
+    let cache = Cache::new(4);
+    let renders = Cell::new(0);
+    let footer = || {
+        renders.set(renders.get() + 1);
+        html! { footer { "Ferris & co" } }
+    };
+    for _ in 0..3 {
+        let result = html! { main {} (cache.entry("footer", footer)) };
+        assert_eq!(
+            result.into_string(),
+            "<main></main><footer>Ferris &amp; co</footer>"
+        );
+    }
+    assert_eq!(renders.get(), 1);
+}
+
+#[test]
+fn keys() {
+    let cache = Cache::new(4);
+    let menu = |lang: &'static str| cache.entry(lang, move || html! { nav { (lang) } });
+    let result = html! { (menu("en")) (menu("de")) (menu("en")) };
+    assert_eq!(
+        result.into_string(),
+        "<nav>en</nav><nav>de</nav><nav>en</nav>"
+    );
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn least_recently_used() {
+    let cache = Cache::new(2);
+    let renders = Cell::new(0);
+    let render = |key: u32| {
+        let _ = html! {
+            (cache.entry(key, || {
+                renders.set(renders.get() + 1);
+                html! { (key) }
+            }))
+        };
+    };
+    render(1);
+    render(2);
+    render(1);
+    // 2 is evicted, since 1 was used more recently
+    render(3);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(renders.get(), 3);
+    render(1);
+    assert_eq!(renders.get(), 3);
+    render(2);
+    assert_eq!(renders.get(), 4);
+}
+
+#[test]
+fn invalidation() {
+    let cache = Cache::new(4);
+    let version = Cell::new(1);
+    let render =
+        || html! { (cache.entry("menu".to_string(), || html! { (version.get()) })) }.into_string();
+    assert_eq!(render(), "1");
+    version.set(2);
+    assert_eq!(render(), "1");
+    cache.invalidate("menu");
+    assert_eq!(render(), "2");
+    version.set(3);
+    cache.retain(|key| key != "menu");
+    assert_eq!(render(), "3");
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn ttl() {
+    let cache = Cache::new(4).with_ttl(Duration::from_millis(20));
+    let renders = Cell::new(0);
+    let render = || {
+        let _ = html! {
+            (cache.entry((), || {
+                renders.set(renders.get() + 1);
+                html! {}
+            }))
+        };
+    };
+    render();
+    render();
+    assert_eq!(renders.get(), 1);
+    thread::sleep(Duration::from_millis(30));
+    render();
+    assert_eq!(renders.get(), 2);
+}
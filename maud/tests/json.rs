@@ -0,0 +1,59 @@
+#![cfg(feature = "serde")]
+
+use maud::{html, Json};
+use std::collections::BTreeMap;
+
+#[test]
+fn script_element() {
+    let data = ["</script>", "<!-- & -->", "\u{2028}\u{2029}"];
+    let result = html! { script type="application/ld+json" { (Json(&data)) } };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r#"<script type="application/ld+json">"#,
+            r#"["\u003c/script\u003e","\u003c!-- \u0026 --\u003e","\u2028\u2029"]"#,
+            "</script>",
+        ),
+    );
+}
+
+#[test]
+fn initializer() {
+    let mut state = BTreeMap::new();
+    state.insert("name", "O'Brien");
+    let result = html! { script { "var state = " (Json(&state)) ";" } };
+    assert_eq!(
+        result.into_string(),
+        r#"<script>var state = {"name":"O'Brien"};</script>"#
+    );
+}
+
+#[test]
+fn attributes() {
+    let data = vec!["a\"b", "c'd"];
+    let result = html! {
+        div data-state=(Json(&data)) onclick={ "load(" (Json(&data)) ")" } {}
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r#"<div data-state="[&quot;a\&quot;b&quot;,&quot;c'd&quot;]""#,
+            r#" onclick="load([&quot;a\&quot;b&quot;,&quot;c'd&quot;])"></div>"#,
+        ),
+    );
+}
+
+#[test]
+fn inside_string() {
+    let result = html! { script { "var s = '" (Json(1)) "';" } };
+    assert_eq!(result.into_string(), "<script>var s = '1';</script>");
+}
+
+#[test]
+fn markup_round_trip() {
+    let markup = html! { p { "Hi & bye" } };
+    let json = serde_json::to_string(&markup).unwrap();
+    assert_eq!(json, r#""<p>Hi &amp; bye</p>""#);
+    let back: maud::Markup = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, markup);
+}
@@ -0,0 +1,105 @@
+use maud::{html, html_static, join, CowMarkup, Markup, PreEscaped};
+
+#[test]
+fn add() {
+    let header = html! { h1 { "Title" } };
+    let mut page = header + html! { p { "Body" } };
+    page += PreEscaped("<hr>");
+    assert_eq!(page.into_string(), "<h1>Title</h1><p>Body</p><hr>");
+}
+
+#[test]
+fn extend_and_collect() {
+    let items: Markup = (1..=3).map(|i| html! { li { (i) } }).collect();
+    assert_eq!(items.into_string(), "<li>1</li><li>2</li><li>3</li>");
+
+    let mut list = html! { li { "0" } };
+    list.extend(vec![html! { li { "<1>" } }]);
+    assert_eq!(list.into_string(), "<li>0</li><li>&lt;1&gt;</li>");
+}
+
+#[test]
+fn join_items() {
+    let names = ["Applejack", "Fluttershy & Rarity"];
+    assert_eq!(
+        join(names.iter(), html! { br; }).into_string(),
+        "Applejack<br>Fluttershy &amp; Rarity"
+    );
+    assert_eq!(join(Vec::<&str>::new(), ", ").into_string(), "");
+    assert_eq!(join([1, 2, 3], " < ").into_string(), "1 &lt; 2 &lt; 3");
+}
+
+#[test]
+fn still_trusted() {
+    let markup = html! { b { "bold" } };
+    let borrowed = PreEscaped("<i>italic</i>");
+    let result = html! {
+        (markup) (&markup) (borrowed) (&&borrowed) (Some(markup.clone())) (maud::DOCTYPE)
+    };
+    assert_eq!(
+        result.into_string(),
+        "<b>bold</b><b>bold</b><i>italic</i><i>italic</i><b>bold</b><!DOCTYPE html>"
+    );
+}
+
+#[test]
+fn comparisons() {
+    use std::collections::HashSet;
+    let a = html! { p { "Hi" } };
+    assert_eq!(a, PreEscaped("<p>Hi</p>".to_string()));
+    assert_ne!(a, html! { p { "Bye" } });
+    assert_eq!(Markup::default(), html! {});
+    let set: HashSet<Markup> = vec![a.clone(), a.clone()].into_iter().collect();
+    assert_eq!(set.len(), 1);
+}
+
+#[test]
+fn as_str() {
+    let markup = html! { p { "Hi" } };
+    assert_eq!(markup.as_str(), "<p>Hi</p>");
+    assert_eq!(markup.as_ref() as &str, "<p>Hi</p>");
+    assert!(markup.starts_with("<p>"));
+    assert_eq!(format!("{}", &*markup), "<p>Hi</p>");
+    assert_eq!(format!("{}", markup.display()), "<p>Hi</p>");
+    assert_eq!(
+        html! { (markup.display()) }.into_string(),
+        "&lt;p&gt;Hi&lt;/p&gt;"
+    );
+}
+
+#[test]
+fn static_markup() {
+    let nav: PreEscaped<&'static str> = html_static! {
+        nav.menu { a href="/" { "Home & away" } br; }
+    };
+    assert_eq!(
+        nav.as_str(),
+        r#"<nav class="menu"><a href="/">Home &amp; away</a><br></nav>"#
+    );
+    let result = html! { (nav) };
+    assert_eq!(result.as_str(), nav.as_str());
+}
+
+#[test]
+fn cow_markup() {
+    fn wrap(body: impl Into<CowMarkup<'static>>) -> Markup {
+        let body = body.into();
+        html! { main { (body) } }
+    }
+    assert_eq!(
+        wrap(html_static! { p { "static" } }).into_string(),
+        "<main><p>static</p></main>"
+    );
+    assert_eq!(
+        wrap(html! { p { (1 + 1) } }).into_string(),
+        "<main><p>2</p></main>"
+    );
+    let borrowed: CowMarkup = maud::DOCTYPE.into();
+    assert!(matches!(borrowed.0, std::borrow::Cow::Borrowed(_)));
+}
+
+#[test]
+fn static_in_const() {
+    const FOOTER: PreEscaped<&str> = html_static! { footer { "Hi" } };
+    assert_eq!(FOOTER.as_str(), "<footer>Hi</footer>");
+}
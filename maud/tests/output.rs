@@ -0,0 +1,119 @@
+use maud::{html, html_to, FmtOutput, IoOutput, Render};
+use std::fmt;
+use std::io;
+
+#[test]
+fn string() {
+    let mut s = String::from("<!-- header -->");
+    let name = "Ferris & co";
+    html_to!(&mut s, {
+        p title=(name) { "Hi, " (name) "!" }
+    })
+    .unwrap();
+    assert_eq!(
+        s,
+        r#"<!-- header --><p title="Ferris &amp; co">Hi, Ferris &amp; co!</p>"#
+    );
+}
+
+#[test]
+fn matches_html() {
+    let items = ["one", "<two>", "three"];
+    let markup = html! {
+        #![escape = "strict"]
+        ul { @for item in &items { li data-item=(item) { (item) } } }
+    };
+    let mut bytes = Vec::new();
+    html_to!(IoOutput::new(&mut bytes), {
+        #![escape = "strict"]
+        ul { @for item in &items { li data-item=(item) { (item) } } }
+    })
+    .unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), markup.into_string());
+}
+
+struct Chunks(Vec<String>);
+
+impl io::Write for Chunks {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.push(String::from_utf8(buf.to_vec()).unwrap());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn writes_when_buffer_fills() {
+    let mut output = IoOutput::with_capacity(Chunks(Vec::new()), 8);
+    html_to!(&mut output, {
+        @for i in 0..3 { p { (i) } }
+    })
+    .unwrap();
+    let chunks = output.into_inner().0;
+    assert_eq!(chunks.concat(), "<p>0</p><p>1</p><p>2</p>");
+    assert!(chunks.len() > 1);
+}
+
+struct Broken;
+
+impl io::Write for Broken {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn output_expression_with_commas() {
+    fn first<A, B>(a: A, _: B) -> A {
+        a
+    }
+    let mut s = String::new();
+    html_to!(first::<&mut String, ()>(&mut s, ()), { p { "Hi" } }).unwrap();
+    assert_eq!(s, "<p>Hi</p>");
+}
+
+#[test]
+fn returns_errors() {
+    let result = html_to!(IoOutput::with_capacity(Broken, 1), {
+        @for _ in 0..3 { p { "Hi" } }
+    });
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+}
+
+struct Greeting<'a>(&'a str);
+
+impl fmt::Display for Greeting<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        html_to!(FmtOutput::new(f), {
+            strong { "Hello, " (self.0) }
+        })
+    }
+}
+
+#[test]
+fn formatter() {
+    assert_eq!(
+        Greeting("<world>").to_string(),
+        "<strong>Hello, &lt;world&gt;</strong>"
+    );
+}
+
+#[test]
+fn render_impls() {
+    struct Name;
+    impl Render for Name {
+        fn render_to(&self, w: &mut String) {
+            w.push_str("<b>Name</b>");
+        }
+    }
+    let mut bytes = Vec::new();
+    html_to!(IoOutput::new(&mut bytes), { p { (Name) } }).unwrap();
+    assert_eq!(bytes, b"<p><b>Name</b></p>");
+}
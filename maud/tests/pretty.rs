@@ -0,0 +1,76 @@
+use maud::{html_pretty, DOCTYPE};
+
+#[test]
+fn nesting() {
+    let items = ["one", "two"];
+    let result = html_pretty! {
+        (DOCTYPE)
+        html {
+            head { title { "Test" } }
+            body {
+                h1 { "Hello, " em { "world" } "!" }
+                ul {
+                    @for item in &items {
+                        li { a href={ "#" (item) } { (item) } }
+                    }
+                }
+                p { "Line one" br; "Line two" }
+            }
+        }
+    };
+    assert_eq!(
+        result.into_string(),
+        r##"<!DOCTYPE html>
+<html>
+  <head>
+    <title>Test</title>
+  </head>
+  <body>
+    <h1>Hello, <em>world</em>!</h1>
+    <ul>
+      <li><a href="#one">one</a></li>
+      <li><a href="#two">two</a></li>
+    </ul>
+    <p>Line one<br>Line two</p>
+  </body>
+</html>"##
+    );
+}
+
+#[test]
+fn preformatted() {
+    let result = html_pretty! {
+        div {
+            pre { code { "fn main() {}" } div { "x" } }
+            textarea { "  keep  " }
+            script { "let x = 1;" }
+        }
+    };
+    assert_eq!(
+        result.into_string(),
+        "<div>\n  <pre><code>fn main() {}</code><div>x</div></pre><textarea>  keep  </textarea>\n  <script>let x = 1;</script>\n</div>"
+    );
+}
+
+#[test]
+fn inline_only() {
+    let result = html_pretty! { span { "a" } b { "b" } };
+    assert_eq!(result.into_string(), "<span>a</span><b>b</b>");
+}
+
+#[test]
+fn dynamic_elements() {
+    let tag = "h2";
+    let result = html_pretty! { section { @element(tag) { "Title" } p { "Body" } } };
+    assert_eq!(
+        result.into_string(),
+        "<section>\n  <h2>Title</h2>\n  <p>Body</p>\n</section>"
+    );
+}
+
+#[test]
+fn dynamic_elements_preformatted() {
+    let tag = "pre";
+    let result = html_pretty! { @element(tag) { p { "a" } p { "b" } } };
+    assert_eq!(result.into_string(), "<pre><p>a</p><p>b</p></pre>");
+}
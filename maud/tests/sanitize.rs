@@ -0,0 +1,50 @@
+#![cfg(feature = "ammonia")]
+
+use maud::{html, Policy, Sanitized};
+
+#[test]
+fn rich_text() {
+    let input = r#"<h1 style="color: red">Hi</h1><img src="x.png" onerror="steal()">"#;
+    let result = html! { (Sanitized::new(input)) };
+    assert_eq!(result.into_string(), r#"<h1>Hi</h1><img src="x.png">"#);
+}
+
+#[test]
+fn comments() {
+    let input = r#"<h1>Big</h1><a href="javascript:steal()">x</a> <a href="https://example.com/">y</a><!-- z -->"#;
+    let result = html! { (Sanitized::with_policy(input, &Policy::comments())) };
+    assert_eq!(
+        result.into_string(),
+        r#"Big<a rel="nofollow noopener noreferrer">x</a> <a href="https://example.com/" rel="nofollow noopener noreferrer">y</a>"#
+    );
+}
+
+#[test]
+fn markdown() {
+    let input = r#"<table><tr><td><img src="data:x" alt="a"></td></tr></table><div>d</div>"#;
+    let result = html! { (Sanitized::with_policy(input, &Policy::markdown())) };
+    assert_eq!(
+        result.into_string(),
+        r#"<table><tbody><tr><td><img alt="a"></td></tr></tbody></table>d"#
+    );
+}
+
+#[test]
+fn custom_policy() {
+    let policy = Policy::comments()
+        .rm_tags(&["a"])
+        .add_tags(&["span"])
+        .add_generic_attributes(&["title"]);
+    let input = r#"<span title="t" class="c"><a href="/">link</a></span>"#;
+    let result = html! { (Sanitized::with_policy(input, &policy)) };
+    assert_eq!(result.into_string(), r#"<span title="t">link</span>"#);
+}
+
+#[test]
+fn attribute() {
+    let result = html! { div title=(Sanitized::new("<b>\"hi\"</b>")) {} };
+    assert_eq!(
+        result.into_string(),
+        r#"<div title="&lt;b&gt;&quot;hi&quot;&lt;/b&gt;"></div>"#
+    );
+}
@@ -124,3 +124,519 @@ fn nested_macro_invocation() {
     let result = html! { (format!("{} is best pony", best_pony)) };
     assert_eq!(result.into_string(), "Pinkie Pie is best pony");
 }
+
+#[test]
+fn url_attributes() {
+    let path = "/a b/\"c\"";
+    let query = "x&y=z";
+    let result = html! { a href={ (path) "?q=" (query) } { "Link" } };
+    assert_eq!(
+        result.into_string(),
+        r#"<a href="/a%20b/%22c%22?q=x%26y%3Dz">Link</a>"#
+    );
+}
+
+#[test]
+fn url_schemes() {
+    let evil = "javascript:alert(1)";
+    let result = html! {
+        a href=(evil) {}
+        img src=(evil);
+        form action={ (evil) } {}
+        a href={ (evil) "/x" } {}
+        a href={ "/go/" (evil) } {}
+        a href={ "java" (evil) } {}
+        a href="https://example.com/" {}
+        a href=("mailto:a@example.com") {}
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r#"<a href="about:invalid"></a>"#,
+            r#"<img src="about:invalid">"#,
+            r#"<form action="about:invalid"></form>"#,
+            r#"<a href="about:invalid/x"></a>"#,
+            r#"<a href="/go/javascript%3Aalert(1)"></a>"#,
+            r#"<a href="javajavascript%3Aalert(1)"></a>"#,
+            r#"<a href="https://example.com/"></a>"#,
+            r#"<a href="mailto:a@example.com"></a>"#,
+        ),
+    );
+}
+
+#[test]
+fn url_schemes_split_across_splices() {
+    let (scheme, rest) = ("javascript", ":alert(1)");
+    let result = html! { a href={ (scheme) (rest) } {} };
+    assert_eq!(
+        result.into_string(),
+        r#"<a href="javascript%3Aalert(1)"></a>"#
+    );
+}
+
+#[test]
+fn url_schemes_completed_by_literals() {
+    let (proto, host) = ("javascript", "%0Aalert(1)");
+    let base = "https://example.com";
+    let result = html! {
+        a href={ (proto) ":alert(1)" } {}
+        a href={ (proto) "://" (host) } {}
+        a href={ (base) "/about?a=1" } {}
+        a href={ (maud::TrustedUrl("javascript")) ":void(0)" } {}
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r#"<a href="about:invalid"></a>"#,
+            r#"<a href="about:invalid"></a>"#,
+            r#"<a href="https://example.com/about?a=1"></a>"#,
+            r#"<a href="about:invalid"></a>"#,
+        ),
+    );
+}
+
+#[test]
+fn url_schemes_split_across_iterations() {
+    let parts = ["javascript", ":alert(1)"];
+    let cond = true;
+    let result = html! {
+        a href={ @for part in &parts { (part) } } {}
+        a href=(parts) {}
+        a href=(vec!["https://example.com", "/a:b"]) {}
+        a href={ @if cond { (parts[0]) } (parts[1]) } {}
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r#"<a href="javascript%3Aalert(1)"></a>"#,
+            r#"<a href="javascript%3Aalert(1)"></a>"#,
+            r#"<a href="https://example.com/a%3Ab"></a>"#,
+            r#"<a href="javascript%3Aalert(1)"></a>"#,
+        ),
+    );
+}
+
+#[test]
+fn url_schemes_allowlist() {
+    let image = "data:image/png;base64,AAAA";
+    let result = html! {
+        #![url_schemes = "https data"]
+        img src=(image);
+        a href=("mailto:a@example.com") {}
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<img src="data:image/png;base64,AAAA"><a href="about:invalid"></a>"#
+    );
+}
+
+#[test]
+fn trusted_url() {
+    use maud::TrustedUrl;
+    let url = "javascript:void(0)";
+    let result = html! {
+        a href=(TrustedUrl(url)) {}
+        a href={ "/" (TrustedUrl("a:b")) } {}
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<a href="javascript:void(0)"></a><a href="/a:b"></a>"#
+    );
+}
+
+#[test]
+fn script_elements() {
+    let name = "</script><script>alert(1)//";
+    let count = 3;
+    let result = html! {
+        script {
+            "var name = \"" (name) "\";"
+            "var count = " (count) ";"
+            "var other = " (name) ";"
+        }
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            "<script>",
+            r#"var name = "\u003C\/script\u003E\u003Cscript\u003Ealert(1)\/\/";"#,
+            "var count = 3;",
+            r"var other = '\u003C\/script\u003E\u003Cscript\u003Ealert(1)\/\/';",
+            "</script>",
+        ),
+    );
+}
+
+#[test]
+fn event_handler_attributes() {
+    let message = "'); stealCookies('";
+    let result = html! { button onclick={ "alert('" (message) "')" } { "Hi" } };
+    assert_eq!(
+        result.into_string(),
+        r#"<button onclick="alert('\u0027); stealCookies(\u0027')">Hi</button>"#
+    );
+}
+
+#[test]
+fn script_branches() {
+    let x = "alert(1)//";
+    for cond in [true, false] {
+        let result = html! {
+            script {
+                @if cond { "var a = '" (x) "';" } @else { "var a = " (x) ";" }
+                "var b = " (x) ";"
+            }
+        };
+        assert_eq!(
+            result.into_string(),
+            concat!(
+                "<script>",
+                r"var a = 'alert(1)\/\/';",
+                r"var b = 'alert(1)\/\/';",
+                "</script>",
+            ),
+        );
+    }
+}
+
+#[test]
+fn script_template_literals() {
+    let x = "${alert(1)}";
+    let y = "alert(1)";
+    let result = html! {
+        script {
+            "var s = `" (x) "`;"
+            "var t = `a${" (y) "}b${ {c: `${d}`}.c }`;"
+            "var u = " (y) ";"
+        }
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            "<script>",
+            r"var s = `\u0024{alert(1)}`;",
+            "var t = `a${'alert(1)'}b${ {c: `${d}`}.c }`;",
+            "var u = 'alert(1)';",
+            "</script>",
+        ),
+    );
+}
+
+#[test]
+fn script_comments_and_regexes() {
+    let y = "alert(1)";
+    let result = html! {
+        script {
+            "// it's a comment\nvar a = " (y) ";"
+            "/* it's a comment */ var b = " (y) ";"
+            "var re = /'/; var c = " (y) ";"
+            "var re2 = /[/']/g; var d = " (y) ";"
+            "var half = e / 2, f = (1) / 'x'.length; var g = " (y) ";"
+            "if (typeof /'/ == 'object') { var h = " (y) "; }"
+        }
+        button onclick={ "f(/'/, " (y) ")" } {}
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            "<script>",
+            "// it's a comment\nvar a = 'alert(1)';",
+            "/* it's a comment */ var b = 'alert(1)';",
+            "var re = /'/; var c = 'alert(1)';",
+            "var re2 = /[/']/g; var d = 'alert(1)';",
+            "var half = e / 2, f = (1) / 'x'.length; var g = 'alert(1)';",
+            "if (typeof /'/ == 'object') { var h = 'alert(1)'; }",
+            "</script>",
+            r#"<button onclick="f(/'/, 'alert(1)')"></button>"#,
+        ),
+    );
+}
+
+#[test]
+fn js_values_keep_string_types() {
+    let result = html! {
+        button onclick={ "f(" ("42") ", " (42) ", " (true) ", " ("true") ")" } {}
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<button onclick="f('42', 42, true, 'true')"></button>"#
+    );
+}
+
+#[test]
+fn style_contexts() {
+    let color = "red; background: url(evil)";
+    let result = html! {
+        style { "p { color: " (color) "; }" }
+        p style={ "color: " (color) } { "Hi" }
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r"<style>p { color: red\3b  background\3a  url\28 evil\29 ; }</style>",
+            r#"<p style="color: red\3b  background\3a  url\28 evil\29 ">Hi</p>"#,
+        ),
+    );
+}
+
+#[test]
+fn pre_escaped_in_script() {
+    use maud::PreEscaped;
+    let result = html! { script { (PreEscaped("if (a < b) { go(); }")) } };
+    assert_eq!(
+        result.into_string(),
+        "<script>if (a < b) { go(); }</script>"
+    );
+}
+
+#[test]
+fn escape_profiles() {
+    let quote = "it's `x`=1";
+    let result = html! {
+        #![escape = "xml"]
+        p title=(quote) { "Don't " (quote) }
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<p title="it&#39;s `x`=1">Don&#39;t it&#39;s `x`=1</p>"#
+    );
+    let result = html! {
+        #![escape = "strict"]
+        p { "a=b " (quote) }
+    };
+    assert_eq!(
+        result.into_string(),
+        "<p>a&#61;b it&#39;s &#96;x&#96;&#61;1</p>"
+    );
+}
+
+#[test]
+fn style_builder() {
+    use maud::Style;
+    let width = 50;
+    let color = "#f00\"><script>";
+    let result = html! {
+        div style=(Style::new()
+            .property("width", format_args!("{}%", width))
+            .property_if(false, "display", "none")
+            .property_if(true, "color", color)
+        ) {}
+        div style=(Style::new()) {}
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<div style="width: 50%; color: #f00\22 \3e \3c script\3e "></div><div style=""></div>"#
+    );
+}
+
+#[test]
+fn classes_builder() {
+    use maud::Classes;
+    let classes = Classes::new()
+        .class("card")
+        .class_if(false, "hidden")
+        .class("card  <wide>")
+        .class("");
+    let collected: Classes = ["a", "b", "a"].iter().collect();
+    let result = html! {
+        div class=(classes) {}
+        div.x class=(collected) {}
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<div class="card &lt;wide&gt;"></div><div class="x a b"></div>"#
+    );
+}
+
+#[test]
+fn charsets() {
+    let name = "Zoë\u{1}";
+    let result = html! {
+        #![charset = "ascii"]
+        #![invalid_chars = "strip"]
+        p title=(name) { "Café " (name) }
+        script { "var s = 'é' + " (name) ";" }
+        style { "p::after { content: 'é'; }" }
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r#"<p title="Zo&#xEB;">Caf&#xE9; Zo&#xEB;</p>"#,
+            r"<script>var s = '\u00E9' + 'Zo\u00EB';</script>",
+            r"<style>p::after { content: '\e9 '; }</style>",
+        ),
+    );
+    let result = html! {
+        #![invalid_chars = "replace"]
+        "\u{FFFF}" (name)
+    };
+    assert_eq!(result.into_string(), "\u{FFFD}Zoë\u{FFFD}");
+}
+
+#[test]
+fn options() {
+    let name: Option<&str> = Some("<Ferris>");
+    let missing: Option<maud::Markup> = None;
+    let result = html! {
+        p title=(name) { (name) (missing) }
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<p title="&lt;Ferris&gt;">&lt;Ferris&gt;</p>"#
+    );
+}
+
+#[test]
+fn optional_attributes() {
+    let title: Option<&str> = Some("<Ferris>");
+    let missing: Option<String> = None;
+    let href = Some("javascript:alert(1)");
+    let result = html! {
+        p title=[title] lang=[missing] { "Hi" }
+        a href=[href] title=[missing.as_deref()] {}
+        input value=[Some(1 + 1)];
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<p title="&lt;Ferris&gt;">Hi</p><a href="about:invalid"></a><input value="2">"#
+    );
+}
+
+#[test]
+fn boolean_attributes() {
+    let expanded = true;
+    let result = html! {
+        button aria-expanded=(expanded) aria-pressed=(!expanded) { "Menu" }
+    };
+    assert_eq!(
+        result.into_string(),
+        r#"<button aria-expanded="true" aria-pressed="false">Menu</button>"#
+    );
+}
+
+#[test]
+fn token_list_attributes() {
+    let rel = vec!["noopener", "noreferrer"];
+    let classes = ["card", "", "<wide>"];
+    let labels = Some(vec!["a", "b"]);
+    let result = html! {
+        a rel=(rel) class=(classes) {}
+        div aria-labelledby=[labels] title=(classes) {}
+        p class=(rel.iter().map(|r| r.to_uppercase())) {}
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r#"<a rel="noopener noreferrer" class="card &lt;wide&gt;"></a>"#,
+            r#"<div aria-labelledby="a b" title="card &lt;wide&gt;"></div>"#,
+            r#"<p class="NOOPENER NOREFERRER"></p>"#,
+        )
+    );
+}
+
+#[test]
+fn collections_in_attributes() {
+    let tags = ["a", "b"];
+    let path = ["/docs", "/intro"];
+    let result = html! {
+        p data-x=(tags) style=(tags) { (tags) }
+        a href=(path) onclick={ "f(" (tags) ")" } {}
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r#"<p data-x="a b" style="ab">ab</p>"#,
+            r#"<a href="/docs/intro" onclick="f(['a','b'])"></a>"#,
+        )
+    );
+}
+
+#[test]
+fn results() {
+    let ok: Result<u32, &str> = Ok(42);
+    let err: Result<u32, &str> = Err("<oops>");
+    let result = html! { (ok) " " (err) };
+    assert_eq!(result.into_string(), "42 &lt;oops&gt;");
+}
+
+#[test]
+fn collections() {
+    let items = vec![html! { li { "one" } }, html! { li { "two" } }];
+    let words = ["a", "<b>", "c"];
+    let result = html! {
+        ul { (items) }
+        p { (words) (&words[1..]) }
+    };
+    assert_eq!(
+        result.into_string(),
+        "<ul><li>one</li><li>two</li></ul><p>a&lt;b&gt;c&lt;b&gt;c</p>"
+    );
+}
+
+#[test]
+fn iterators() {
+    let numbers = [1, 2, 3];
+    let result = html! {
+        ol { (numbers.iter().map(|n| html! { li { (n) } })) }
+        // Iterators that also implement `Display` render as before
+        p { ('ß'.to_uppercase()) }
+    };
+    assert_eq!(
+        result.into_string(),
+        "<ol><li>1</li><li>2</li><li>3</li></ol><p>SS</p>"
+    );
+}
+
+#[test]
+fn script_collections() {
+    let numbers = vec![1, 2];
+    let words = ["a", "</script>"];
+    let none: Option<i32> = None;
+    let result = html! {
+        script {
+            "var a = " (numbers) "; var b = " (words) "; var c = " (none) "; var d = " (Some(5))
+            "; var e = " (Some("5")) "; var f = " (numbers.iter().map(|n| n * 2)) ";"
+        }
+        button onclick={ "f(" (&numbers) ", " (&words[..1]) ")" } {}
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r"<script>var a = [1,2]; var b = ['a','\u003C\/script\u003E']; var c = null; ",
+            r"var d = 5; var e = '5'; var f = ['2','4'];</script>",
+            r#"<button onclick="f([1,2], ['a'])"></button>"#,
+        ),
+    );
+}
+
+#[test]
+fn binary_expressions() {
+    let n = 1;
+    let name = "Ferris";
+    let result = html! { (n + 1) " " (n * 2 == 2) " " (n == 1) " " (name.to_owned() + "!") };
+    assert_eq!(result.into_string(), "2 true true Ferris!");
+}
+
+#[test]
+fn format_specs() {
+    let price = 4.5678;
+    let name = "<Ferris>";
+    let result = html! {
+        span data-price=(price; ".2") { (price; ".1") }
+        pre { (42; ">5") "|" (name; "?") }
+    };
+    assert_eq!(
+        result.into_string(),
+        concat!(
+            r#"<span data-price="4.57">4.6</span>"#,
+            r#"<pre>   42|&quot;&lt;Ferris&gt;&quot;</pre>"#,
+        )
+    );
+}
+
+#[test]
+fn format_args() {
+    let (x, y) = (1, 2);
+    let result = html! { p { (format_args!("{} < {}", x, y)) } };
+    assert_eq!(result.into_string(), "<p>1 &lt; 2</p>");
+}
@@ -0,0 +1,147 @@
+#![cfg(feature = "stream")]
+
+use futures_core::Stream;
+use maud::{html, html_stream};
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+/// Polls the stream to completion, collecting its chunks.
+fn receive(mut stream: impl Stream<Item = String> + Unpin) -> Vec<String> {
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut chunks = Vec::new();
+    loop {
+        match Pin::new(&mut stream).poll_next(&mut cx) {
+            Poll::Ready(Some(chunk)) => chunks.push(chunk),
+            Poll::Ready(None) => return chunks,
+            Poll::Pending => panic!("stream is waiting on nothing"),
+        }
+    }
+}
+
+#[test]
+fn matches_html() {
+    let rows = vec!["one", "<two>", "three"];
+    let markup = html! {
+        table { @for row in &rows { tr { td { (row) } } } }
+    };
+    let stream = html_stream! {
+        table { @for row in &rows { tr { td { (row) } } } }
+    };
+    assert_eq!(receive(stream).concat(), markup.into_string());
+}
+
+#[test]
+fn one_chunk_when_small() {
+    let stream = html_stream! {
+        ul { @for i in 0..3 { li { (i) } } }
+    };
+    assert_eq!(receive(stream), ["<ul><li>0</li><li>1</li><li>2</li></ul>"]);
+}
+
+#[test]
+fn flushes_at_iterations() {
+    let stream = html_stream! {
+        ul { @for _ in 0..3 { li { "x" } } }
+    }
+    .chunk_size(0);
+    assert_eq!(
+        receive(stream),
+        ["<ul><li>x</li>", "<li>x</li>", "<li>x</li>", "</ul>"]
+    );
+}
+
+#[test]
+fn flushes_after_splices() {
+    let name = "Ferris";
+    let stream = html_stream! {
+        p { "Hi, " (name) "!" }
+    }
+    .chunk_size(8);
+    assert_eq!(receive(stream), ["<p>Hi, Ferris", "!</p>"]);
+}
+
+#[test]
+fn borrows_variables() {
+    let rows = vec!["one", "two"];
+    let stream = html_stream! {
+        @for row in &rows { p { (row) } }
+    };
+    assert_eq!(receive(stream), ["<p>one</p><p>two</p>"]);
+    assert_eq!(rows.len(), 2);
+}
+
+#[test]
+fn moves_variables() {
+    fn handler(name: &str) -> impl Stream<Item = String> + Unpin {
+        let greeting = format!("Hi, {}!", name);
+        html_stream!(move {
+            p { (greeting) }
+            @for c in greeting.chars().take(2) { b { (c) } }
+        })
+    }
+    assert_eq!(
+        receive(handler("Ferris")).concat(),
+        "<p>Hi, Ferris!</p><b>H</b><b>i</b>"
+    );
+}
+
+#[test]
+fn wakes_after_each_chunk() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let counter = Arc::new(Counter(AtomicUsize::new(0)));
+    let waker = Waker::from(Arc::clone(&counter));
+    let mut cx = Context::from_waker(&waker);
+    let mut stream = html_stream! {
+        @for _ in 0..2 { p { "x" } }
+    }
+    .chunk_size(0);
+    let first = Pin::new(&mut stream).poll_next(&mut cx);
+    assert_eq!(first, Poll::Ready(Some("<p>x</p>".to_string())));
+    assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn flushes_at_chunk_size() {
+    let mut n = 0;
+    let stream = html_stream! {
+        @while n < 6 {
+            "ab"
+            ({ n += 1; "" })
+        }
+    }
+    .chunk_size(4);
+    assert_eq!(receive(stream), ["abab", "abab", "abab"]);
+}
+
+#[test]
+fn lazy() {
+    use std::cell::Cell;
+    let rendered = &Cell::new(0);
+    let mut stream = html_stream! {
+        @for i in 0..3 { p { (i) ({ rendered.set(rendered.get() + 1); "" }) } }
+    }
+    .chunk_size(0);
+    assert_eq!(rendered.get(), 0);
+    let mut cx = Context::from_waker(Waker::noop());
+    let first = Pin::new(&mut stream).poll_next(&mut cx);
+    assert_eq!(first, Poll::Ready(Some("<p>0".to_string())));
+    assert_eq!(rendered.get(), 0);
+    let second = Pin::new(&mut stream).poll_next(&mut cx);
+    assert_eq!(second, Poll::Ready(Some("</p>".to_string())));
+    assert_eq!(rendered.get(), 1);
+}
@@ -0,0 +1,115 @@
+use maud::{html, try_html, Context, Markup, TryRender};
+use std::fmt::{self, Write};
+use std::num::ParseIntError;
+
+#[test]
+fn ok() {
+    let result: Result<Markup, ParseIntError> = try_html! {
+        @let n: u32 = "42".parse()?;
+        p { ("<") ("7".parse::<u32>()? + n) }
+    };
+    assert_eq!(result.unwrap().into_string(), "<p>&lt;49</p>");
+}
+
+#[test]
+fn question_mark() {
+    let result: Result<Markup, ParseIntError> = try_html! {
+        p { "before" }
+        p { ("pinkie".parse::<u32>()?) }
+        p { "after" }
+    };
+    assert!(result.is_err());
+}
+
+#[derive(Debug, PartialEq)]
+enum PageError {
+    Missing(&'static str),
+    Format,
+}
+
+impl From<fmt::Error> for PageError {
+    fn from(_: fmt::Error) -> PageError {
+        PageError::Format
+    }
+}
+
+struct Section(&'static str, Option<&'static str>);
+
+impl TryRender for Section {
+    type Error = PageError;
+
+    fn try_render(&self) -> Result<Markup, PageError> {
+        let body = self.1.ok_or(PageError::Missing(self.0))?;
+        Ok(html! { section { h2 { (self.0) } p { (body) } } })
+    }
+}
+
+struct Fails;
+
+impl TryRender for Fails {
+    type Error = fmt::Error;
+
+    fn try_render_to(&self, _: &mut String) -> fmt::Result {
+        Err(fmt::Error)
+    }
+}
+
+/// Writes the position it is spliced into.
+struct Where;
+
+impl TryRender for Where {
+    type Error = fmt::Error;
+
+    fn try_render_to_context(&self, context: Context, buffer: &mut String) -> fmt::Result {
+        write!(buffer, "{:?}", context.position())
+    }
+}
+
+fn page(sections: &[Section]) -> Result<Markup, PageError> {
+    try_html! {
+        @for section in sections {
+            (section)
+        }
+    }
+}
+
+#[test]
+fn try_render() {
+    let result = page(&[Section("Intro", Some("Hi & welcome"))]);
+    assert_eq!(
+        result.unwrap().into_string(),
+        "<section><h2>Intro</h2><p>Hi &amp; welcome</p></section>"
+    );
+    let result = page(&[Section("Intro", Some("Hi")), Section("Outro", None)]);
+    assert_eq!(result.unwrap_err(), PageError::Missing("Outro"));
+}
+
+#[test]
+fn converts_errors() {
+    let result: Result<Markup, PageError> = try_html! { p { (Fails) } };
+    assert_eq!(result.unwrap_err(), PageError::Format);
+}
+
+#[test]
+fn render_types() {
+    let name: Option<&str> = Some("Ferris");
+    let result: Result<Markup, fmt::Error> = try_html! {
+        p title=(name) { "Hi " (name) ", " (html! { b { "hello" } }) (1..4) }
+    };
+    assert_eq!(
+        result.unwrap().into_string(),
+        r#"<p title="Ferris">Hi Ferris, <b>hello</b>123</p>"#
+    );
+}
+
+#[test]
+fn passes_context() {
+    let result: Result<Markup, fmt::Error> = try_html! {
+        p title=(Where) { (Where) }
+        a href=(Where) {}
+    };
+    assert_eq!(
+        result.unwrap().into_string(),
+        r#"<p title="Attribute">Html</p><a href="Url"></a>"#
+    );
+}
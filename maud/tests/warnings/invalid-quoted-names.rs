@@ -0,0 +1,9 @@
+use maud::html;
+
+fn main() {
+    html! {
+        button "on click"="go()" {}
+        a "x&amp"="1" {}
+        @element("1st") {}
+    };
+}
@@ -0,0 +1,7 @@
+use maud::html;
+
+fn main() {
+    html! {
+        a href="/" ..([("href", "/elsewhere"), ("title", "Elsewhere")]) { "Home" }
+    };
+}
@@ -0,0 +1,7 @@
+use maud::html_static;
+
+fn main() {
+    html_static! {
+        p { "One plus one is " (1 + 1) }
+    };
+}
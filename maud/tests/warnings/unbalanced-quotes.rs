@@ -0,0 +1,20 @@
+use maud::html;
+
+fn main() {
+    let cond = true;
+    let x = "alert(1)//";
+    html! {
+        script {
+            @if cond { "var a = '" } @else { "var a = " }
+            (x) ";"
+        }
+        button onclick={ @for _ in 0..2 { "f('" } (x) } {}
+        script {
+            @match cond {
+                true => { "var b = \"" }
+                false => { "var b = \"" }
+            }
+            (x) "\";"
+        }
+    };
+}
@@ -0,0 +1,11 @@
+use maud::html;
+
+fn main() {
+    let x = "alert(1)";
+    html! {
+        script { "var re = /a" (x) "/;" }
+        script { "if (a) {} /b/.test(c) ? " (x) " : 0;" }
+        script { "var s = '" (x) }
+        button onclick={ "f(`" (x) } {}
+    };
+}
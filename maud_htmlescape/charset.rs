@@ -0,0 +1,160 @@
+use std::borrow::Cow;
+use std::char;
+use std::fmt::Write;
+
+use crate::names::is_noncharacter;
+use crate::Profile;
+
+/// Which characters may appear in the output.
+///
+/// By default, all characters are written as UTF-8. A charset can
+/// change this in two ways:
+///
+/// * With [`Charset::ASCII`], every non-ASCII character is written as a
+///   numeric character reference such as `&#xE9;`, so that the output
+///   is pure ASCII.
+/// * With [`Charset::with_invalid_chars`], characters that are not
+///   allowed in HTML or XML 1.0 are removed or replaced. These are the
+///   C0 control characters other than tab, line feed and carriage
+///   return; `DEL` and the C1 control characters; and noncharacters
+///   such as `U+FFFE`.
+///
+/// # Example
+///
+/// ```rust
+/// use maud_htmlescape::{Charset, Escaper, InvalidChars};
+/// use std::fmt::Write;
+/// let charset = Charset::ASCII.with_invalid_chars(InvalidChars::Strip);
+/// let mut s = String::new();
+/// write!(Escaper::new(&mut s).with_charset(charset), "caf\u{e9}\u{1}").unwrap();
+/// assert_eq!(s, "caf&#xE9;");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Charset {
+    ascii_only: bool,
+    invalid_chars: InvalidChars,
+}
+
+/// What to do with characters that are not allowed in HTML or XML.
+///
+/// See [`Charset`] for which characters these are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum InvalidChars {
+    /// Write them unchanged. This is the default.
+    #[default]
+    Keep,
+    /// Remove them.
+    Strip,
+    /// Replace them with `U+FFFD REPLACEMENT CHARACTER`.
+    Replace,
+}
+
+impl Charset {
+    /// Writes all characters as UTF-8. This is the default.
+    pub const UTF8: Charset = Charset {
+        ascii_only: false,
+        invalid_chars: InvalidChars::Keep,
+    };
+
+    /// Writes non-ASCII characters as numeric character references.
+    pub const ASCII: Charset = Charset {
+        ascii_only: true,
+        invalid_chars: InvalidChars::Keep,
+    };
+
+    /// Returns a copy of this charset that handles invalid characters
+    /// in the given way.
+    pub const fn with_invalid_chars(self, invalid_chars: InvalidChars) -> Charset {
+        Charset {
+            invalid_chars,
+            ..self
+        }
+    }
+
+    /// Returns whether non-ASCII characters are escaped.
+    pub fn is_ascii_only(self) -> bool {
+        self.ascii_only
+    }
+
+    /// Returns how invalid characters are handled.
+    pub fn invalid_chars(self) -> InvalidChars {
+        self.invalid_chars
+    }
+
+    /// Returns the character to write in place of `c`, or `None` if it
+    /// should be removed.
+    ///
+    /// This only applies the [`InvalidChars`] rule. Escaping non-ASCII
+    /// characters is up to the caller, since it depends on where the
+    /// text ends up.
+    pub fn filter(self, c: char) -> Option<char> {
+        match self.invalid_chars {
+            InvalidChars::Keep => Some(c),
+            _ if !is_invalid(c) => Some(c),
+            InvalidChars::Strip => None,
+            InvalidChars::Replace => Some(char::REPLACEMENT_CHARACTER),
+        }
+    }
+
+    /// Applies [`Charset::filter`] to every character in the string.
+    pub(crate) fn filter_str(self, s: &str) -> Cow<'_, str> {
+        if self.invalid_chars == InvalidChars::Keep || !s.chars().any(is_invalid) {
+            Cow::Borrowed(s)
+        } else {
+            Cow::Owned(s.chars().filter_map(|c| self.filter(c)).collect())
+        }
+    }
+}
+
+/// Returns whether the given character is not allowed in HTML or XML.
+fn is_invalid(c: char) -> bool {
+    match c {
+        '\t' | '\n' | '\r' => false,
+        '\0'..='\x1f' | '\x7f'..='\u{9f}' => true,
+        c => is_noncharacter(c),
+    }
+}
+
+/// Escapes the given bytes character by character, appending the
+/// result to `buffer`.
+///
+/// This is the slow path for charsets other than [`Charset::UTF8`].
+/// Bytes that are not valid UTF-8 are treated as invalid characters.
+pub(crate) fn escape_chars_to_vec(
+    bytes: &[u8],
+    profile: Profile,
+    charset: Charset,
+    buffer: &mut Vec<u8>,
+) {
+    let push_char = |buffer: &mut Vec<u8>, c: char| {
+        if c.is_ascii() {
+            match profile.escape_byte(c as u8) {
+                Some(escaped) => buffer.extend_from_slice(escaped.as_bytes()),
+                None => buffer.push(c as u8),
+            }
+        } else if charset.ascii_only {
+            let mut reference = String::new();
+            let _ = write!(reference, "&#x{:X};", c as u32);
+            buffer.extend_from_slice(reference.as_bytes());
+        } else {
+            buffer.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+    };
+    buffer.reserve(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars().filter_map(|c| charset.filter(c)) {
+            push_char(buffer, c);
+        }
+        if !chunk.invalid().is_empty() {
+            match charset.invalid_chars {
+                // Raw bytes can't be kept in ASCII output
+                InvalidChars::Keep if charset.ascii_only => {
+                    push_char(buffer, char::REPLACEMENT_CHARACTER)
+                }
+                InvalidChars::Keep => buffer.extend_from_slice(chunk.invalid()),
+                InvalidChars::Strip => {}
+                InvalidChars::Replace => push_char(buffer, char::REPLACEMENT_CHARACTER),
+            }
+        }
+    }
+}
@@ -6,57 +6,964 @@
 
 #![doc(html_root_url = "https://docs.rs/maud_htmlescape/0.17.0")]
 
+use std::convert::Infallible;
 use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::str;
+
+mod charset;
+mod entities;
+mod names;
+mod unescape;
+
+pub use crate::charset::{Charset, InvalidChars};
+pub use crate::names::{
+    is_script_attribute, is_valid_attribute_name, is_valid_element_name, TOKEN_LIST_ATTRIBUTES,
+    URL_ATTRIBUTES, VOID_ELEMENTS,
+};
+pub use crate::unescape::{unescape, unescape_attribute};
+
+/// A set of rules for which characters to escape.
+///
+/// All profiles escape the following characters:
+///
+/// * `&` is escaped as `&amp;`
+/// * `<` is escaped as `&lt;`
+/// * `>` is escaped as `&gt;`
+/// * `"` is escaped as `&quot;`
+///
+/// Some profiles escape more characters on top of these. All other
+/// characters are passed through unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Profile {
+    /// Escapes only the characters listed above. This is the default.
+    #[default]
+    Minimal,
+    /// Also escapes `'` as `&#39;`.
+    ///
+    /// This suits XML and XHTML consumers, and values that end up in
+    /// single-quoted attributes.
+    Xml,
+    /// Also escapes `'` as `&#39;`, `` ` `` as `&#96;` and `=` as `&#61;`.
+    ///
+    /// This covers the characters that can end an unquoted attribute
+    /// value, or that some legacy browsers treat as quotes.
+    Strict,
+}
+
+impl Profile {
+    /// Returns the escape sequence for the given byte, if it needs one.
+    fn escape_byte(self, b: u8) -> Option<&'static str> {
+        match (b, self) {
+            (b'&', _) => Some("&amp;"),
+            (b'<', _) => Some("&lt;"),
+            (b'>', _) => Some("&gt;"),
+            (b'"', _) => Some("&quot;"),
+            (b'\'', Profile::Xml) | (b'\'', Profile::Strict) => Some("&#39;"),
+            (b'`', Profile::Strict) => Some("&#96;"),
+            (b'=', Profile::Strict) => Some("&#61;"),
+            _ => None,
+        }
+    }
+
+    /// Returns whether any byte in the given word might need escaping.
+    ///
+    /// This checks all the bytes in the word at once (SWAR). It may
+    /// return a false positive, but never a false negative.
+    fn word_has_special(self, word: usize) -> bool {
+        match self {
+            Profile::Minimal => word_has_any(word, b"&<>\""),
+            Profile::Xml => word_has_any(word, b"&<>\"'"),
+            Profile::Strict => word_has_any(word, b"&<>\"'`="),
+        }
+    }
+}
+
+const WORD_SIZE: usize = std::mem::size_of::<usize>();
+
+#[inline(always)]
+fn word_has_any<const N: usize>(word: usize, bytes: &[u8; N]) -> bool {
+    const LO: usize = usize::MAX / 0xff;
+    const HI: usize = LO << 7;
+    bytes.iter().any(|&b| {
+        // Bytes equal to `b` become zero; then test for a zero byte
+        let x = word ^ (LO * b as usize);
+        x.wrapping_sub(LO) & !x & HI != 0
+    })
+}
+
+fn read_word(bytes: &[u8], i: usize) -> usize {
+    let mut word = [0; WORD_SIZE];
+    word.copy_from_slice(&bytes[i..i + WORD_SIZE]);
+    usize::from_ne_bytes(word)
+}
 
 /// An adapter that escapes HTML special characters.
 ///
-/// The following characters are escaped:
+/// By default, this uses the [`Profile::Minimal`] rules:
 ///
 /// * `&` is escaped as `&amp;`
 /// * `<` is escaped as `&lt;`
 /// * `>` is escaped as `&gt;`
 /// * `"` is escaped as `&quot;`
 ///
-/// All other characters are passed through unchanged.
+/// All other characters are passed through unchanged. Use
+/// [`Escaper::with_profile`] to escape more characters, and
+/// [`Escaper::with_charset`] to escape non-ASCII characters.
 ///
 /// **Note:** In versions prior to 0.13, the single quote (`'`) was
-/// escaped as well.
+/// escaped as well. To keep doing this, use [`Profile::Xml`].
 ///
 /// # Example
 ///
-/// ```rust,ignore
+/// ```rust
+/// use maud_htmlescape::Escaper;
 /// use std::fmt::Write;
 /// let mut s = String::new();
 /// write!(Escaper::new(&mut s), "<script>launchMissiles()</script>").unwrap();
 /// assert_eq!(s, "&lt;script&gt;launchMissiles()&lt;/script&gt;");
 /// ```
-pub struct Escaper<'a>(&'a mut String);
+pub struct Escaper<'a> {
+    buffer: &'a mut String,
+    profile: Profile,
+    charset: Charset,
+}
 
 impl<'a> Escaper<'a> {
     /// Creates an `Escaper` from a `String`.
     pub fn new(buffer: &'a mut String) -> Escaper<'a> {
-        Escaper(buffer)
+        Escaper::with_profile(buffer, Profile::Minimal)
+    }
+
+    /// Creates an `Escaper` from a `String`, which follows the rules of
+    /// the given profile.
+    pub fn with_profile(buffer: &'a mut String, profile: Profile) -> Escaper<'a> {
+        Escaper {
+            buffer,
+            profile,
+            charset: Charset::UTF8,
+        }
+    }
+
+    /// Makes the `Escaper` follow the rules of the given charset.
+    pub fn with_charset(self, charset: Charset) -> Escaper<'a> {
+        Escaper { charset, ..self }
     }
 }
 
 impl<'a> fmt::Write for Escaper<'a> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for b in s.bytes() {
-            match b {
-                b'&' => self.0.push_str("&amp;"),
-                b'<' => self.0.push_str("&lt;"),
-                b'>' => self.0.push_str("&gt;"),
-                b'"' => self.0.push_str("&quot;"),
-                _ => unsafe { self.0.as_mut_vec().push(b) },
+        // SAFETY: Valid UTF-8 input is only ever split between
+        // characters, so the buffer stays valid UTF-8
+        escape_to_vec(s.as_bytes(), self.profile, self.charset, unsafe {
+            self.buffer.as_mut_vec()
+        });
+        Ok(())
+    }
+}
+
+/// An adapter that escapes HTML special characters into a `Vec<u8>`.
+///
+/// This follows the same rules as [`Escaper`]. It implements both
+/// `fmt::Write` and `io::Write`, and never fails.
+pub struct VecEscaper<'a> {
+    buffer: &'a mut Vec<u8>,
+    profile: Profile,
+    charset: Charset,
+}
+
+impl<'a> VecEscaper<'a> {
+    /// Creates a `VecEscaper` from a `Vec<u8>`.
+    pub fn new(buffer: &'a mut Vec<u8>) -> VecEscaper<'a> {
+        VecEscaper::with_profile(buffer, Profile::Minimal)
+    }
+
+    /// Creates a `VecEscaper` from a `Vec<u8>`, which follows the rules
+    /// of the given profile.
+    pub fn with_profile(buffer: &'a mut Vec<u8>, profile: Profile) -> VecEscaper<'a> {
+        VecEscaper {
+            buffer,
+            profile,
+            charset: Charset::UTF8,
+        }
+    }
+
+    /// Makes the `VecEscaper` follow the rules of the given charset.
+    pub fn with_charset(self, charset: Charset) -> VecEscaper<'a> {
+        VecEscaper { charset, ..self }
+    }
+}
+
+impl<'a> fmt::Write for VecEscaper<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        escape_to_vec(s.as_bytes(), self.profile, self.charset, self.buffer);
+        Ok(())
+    }
+}
+
+impl<'a> io::Write for VecEscaper<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        escape_to_vec(buf, self.profile, self.charset, self.buffer);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An adapter that escapes HTML special characters into any
+/// `fmt::Write`, such as a `fmt::Formatter`.
+///
+/// This follows the same rules as [`Escaper`].
+///
+/// # Example
+///
+/// ```rust
+/// use maud_htmlescape::FmtEscaper;
+/// use std::fmt::{self, Write};
+///
+/// struct Bold<'a>(&'a str);
+///
+/// impl<'a> fmt::Display for Bold<'a> {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         f.write_str("<b>")?;
+///         FmtEscaper::new(&mut *f).write_str(self.0)?;
+///         f.write_str("</b>")
+///     }
+/// }
+///
+/// assert_eq!(Bold("x < y").to_string(), "<b>x &lt; y</b>");
+/// ```
+pub struct FmtEscaper<W: fmt::Write> {
+    inner: W,
+    profile: Profile,
+    charset: Charset,
+}
+
+impl<W: fmt::Write> FmtEscaper<W> {
+    /// Creates a `FmtEscaper` that writes to the given `fmt::Write`.
+    pub fn new(inner: W) -> FmtEscaper<W> {
+        FmtEscaper::with_profile(inner, Profile::Minimal)
+    }
+
+    /// Creates a `FmtEscaper` that writes to the given `fmt::Write`,
+    /// and follows the rules of the given profile.
+    pub fn with_profile(inner: W, profile: Profile) -> FmtEscaper<W> {
+        FmtEscaper {
+            inner,
+            profile,
+            charset: Charset::UTF8,
+        }
+    }
+
+    /// Makes the `FmtEscaper` follow the rules of the given charset.
+    pub fn with_charset(self, charset: Charset) -> FmtEscaper<W> {
+        FmtEscaper { charset, ..self }
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for FmtEscaper<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let inner = &mut self.inner;
+        escape_runs(s.as_bytes(), self.profile, self.charset, |piece, _| {
+            // SAFETY: `escape_runs` only splits valid UTF-8 input
+            // between characters, so every piece is valid UTF-8
+            inner.write_str(unsafe { str::from_utf8_unchecked(piece) })
+        })
+    }
+}
+
+/// An adapter that escapes HTML special characters into any
+/// `io::Write`, such as a file or socket.
+///
+/// This follows the same rules as [`Escaper`]. Bytes that are not
+/// valid UTF-8 are passed through unchanged.
+///
+/// Each piece of plain text and each escape sequence is written with a
+/// separate call, so wrapping the writer in an `io::BufWriter` is
+/// recommended.
+///
+/// # Example
+///
+/// ```rust
+/// use maud_htmlescape::IoEscaper;
+/// use std::io::Write;
+/// let mut out = Vec::new();
+/// write!(IoEscaper::new(&mut out), "{}", "Fish & chips").unwrap();
+/// assert_eq!(out, b"Fish &amp; chips");
+/// ```
+pub struct IoEscaper<W: io::Write> {
+    inner: W,
+    profile: Profile,
+    charset: Charset,
+}
+
+impl<W: io::Write> IoEscaper<W> {
+    /// Creates an `IoEscaper` that writes to the given `io::Write`.
+    pub fn new(inner: W) -> IoEscaper<W> {
+        IoEscaper::with_profile(inner, Profile::Minimal)
+    }
+
+    /// Creates an `IoEscaper` that writes to the given `io::Write`, and
+    /// follows the rules of the given profile.
+    pub fn with_profile(inner: W, profile: Profile) -> IoEscaper<W> {
+        IoEscaper {
+            inner,
+            profile,
+            charset: Charset::UTF8,
+        }
+    }
+
+    /// Makes the `IoEscaper` follow the rules of the given charset.
+    ///
+    /// Bytes that are not valid UTF-8 count as invalid characters.
+    pub fn with_charset(self, charset: Charset) -> IoEscaper<W> {
+        IoEscaper { charset, ..self }
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: io::Write> io::Write for IoEscaper<W> {
+    /// Escapes and writes the given bytes.
+    ///
+    /// This returns how many bytes of the input have been written out,
+    /// which can be fewer than `buf.len()` if the underlying writer
+    /// fails or accepts only part of a run of plain text. An error is
+    /// returned only if nothing was written.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        // The length of the input whose output has been written
+        let mut consumed = 0;
+        let result = escape_runs(buf, self.profile, self.charset, |piece, input| {
+            if piece.as_ptr() == buf[input.start..].as_ptr() {
+                // Plain text is a slice of the input, so a short write
+                // still consumes part of it
+                let n = inner.write(piece)?;
+                consumed = input.start + n;
+                if n < piece.len() {
+                    return Err(None);
+                }
+            } else {
+                inner.write_all(piece)?;
+                consumed = input.end;
             }
+            Ok(())
+        });
+        match result {
+            Ok(()) => Ok(buf.len()),
+            Err(Some(error)) if consumed == 0 => Err(error),
+            Err(_) => Ok(consumed),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Calls `f` with the index and escape sequence of each byte that needs
+/// escaping.
+///
+/// This skips over plain text a word at a time, and only looks at
+/// individual bytes in words that might need escaping.
+#[inline(always)]
+fn for_each_escape<E>(
+    bytes: &[u8],
+    profile: Profile,
+    mut f: impl FnMut(usize, &'static str) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut i = 0;
+    while i < bytes.len() {
+        if i + WORD_SIZE <= bytes.len() && !profile.word_has_special(read_word(bytes, i)) {
+            i += WORD_SIZE;
+            continue;
         }
+        if let Some(escaped) = profile.escape_byte(bytes[i]) {
+            f(i, escaped)?;
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// Escapes the given bytes, appending the result to `buffer`.
+fn escape_to_vec(bytes: &[u8], profile: Profile, charset: Charset, buffer: &mut Vec<u8>) {
+    if charset != Charset::UTF8 {
+        return charset::escape_chars_to_vec(bytes, profile, charset, buffer);
+    }
+    buffer.reserve(bytes.len());
+    // Start of the run of plain text that hasn't been copied yet
+    let mut start = 0;
+    let _ = for_each_escape(bytes, profile, |i, escaped| {
+        buffer.extend_from_slice(&bytes[start..i]);
+        buffer.extend_from_slice(escaped.as_bytes());
+        start = i + 1;
+        Ok::<(), Infallible>(())
+    });
+    buffer.extend_from_slice(&bytes[start..]);
+}
+
+/// Escapes the given bytes, passing each run of plain text and each
+/// escape sequence to `write`, along with the range of the input that
+/// it comes from.
+///
+/// This makes fewer, larger writes than `escape_to_vec`, which suits
+/// writers that aren't in-memory buffers. Runs of plain text are passed
+/// as slices of `bytes`.
+fn escape_runs<E>(
+    bytes: &[u8],
+    profile: Profile,
+    charset: Charset,
+    mut write: impl FnMut(&[u8], Range<usize>) -> Result<(), E>,
+) -> Result<(), E> {
+    if charset != Charset::UTF8 {
+        let mut buffer = Vec::new();
+        let mut start = 0;
+        for chunk in bytes.utf8_chunks() {
+            let end = start + chunk.valid().len() + chunk.invalid().len();
+            buffer.clear();
+            charset::escape_chars_to_vec(&bytes[start..end], profile, charset, &mut buffer);
+            if !buffer.is_empty() {
+                write(&buffer, start..end)?;
+            }
+            start = end;
+        }
+        return Ok(());
+    }
+    let mut start = 0;
+    for_each_escape(bytes, profile, |i, escaped| {
+        if start < i {
+            write(&bytes[start..i], start..i)?;
+        }
+        write(escaped.as_bytes(), i..i + 1)?;
+        start = i + 1;
         Ok(())
+    })?;
+    if start < bytes.len() {
+        write(&bytes[start..], start..bytes.len())?;
     }
+    Ok(())
+}
+
+/// An adapter that escapes text for use in a CSS property value.
+///
+/// The following characters are passed through unchanged:
+///
+/// * ASCII letters and digits
+/// * Spaces, and `#%+,-._`
+/// * Non-ASCII characters
+///
+/// All other ASCII characters, including quotes, semicolons, braces,
+/// parentheses and backslashes, are written as CSS escape sequences
+/// such as `\3b `. A NUL character is replaced with `U+FFFD`.
+///
+/// This means the value can't end the declaration, start a comment,
+/// or call a function such as `url()`. The output contains no HTML
+/// special characters, so it is also safe in a `style` attribute.
+///
+/// # Example
+///
+/// ```rust
+/// use maud_htmlescape::CssEscaper;
+/// use std::fmt::Write;
+/// let mut s = String::new();
+/// write!(CssEscaper::new(&mut s), "red; background: url(x)").unwrap();
+/// assert_eq!(s, r"red\3b  background\3a  url\28 x\29 ");
+/// ```
+pub struct CssEscaper<'a> {
+    buffer: &'a mut String,
+    charset: Charset,
+}
+
+impl<'a> CssEscaper<'a> {
+    /// Creates a `CssEscaper` from a `String`.
+    pub fn new(buffer: &'a mut String) -> CssEscaper<'a> {
+        CssEscaper {
+            buffer,
+            charset: Charset::UTF8,
+        }
+    }
+
+    /// Makes the `CssEscaper` follow the rules of the given charset.
+    ///
+    /// With [`Charset::ASCII`], non-ASCII characters are written as CSS
+    /// escape sequences too.
+    pub fn with_charset(self, charset: Charset) -> CssEscaper<'a> {
+        CssEscaper { charset, ..self }
+    }
+}
+
+impl<'a> fmt::Write for CssEscaper<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let charset = self.charset;
+        for c in s.chars().filter_map(|c| charset.filter(c)) {
+            match c {
+                'A'..='Z'
+                | 'a'..='z'
+                | '0'..='9'
+                | ' '
+                | '#'
+                | '%'
+                | '+'
+                | ','
+                | '-'
+                | '.'
+                | '_' => self.buffer.push(c),
+                '\0' => self.buffer.push('\u{FFFD}'),
+                c if c.is_ascii() || charset.is_ascii_only() => {
+                    write!(self.buffer, "\\{:x} ", c as u32)?
+                }
+                c => self.buffer.push(c),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The kind of position in a document that a value is inserted into.
+///
+/// Each position has its own escaping rules. See [`ContextEscaper`]
+/// for how they are applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Position {
+    /// The text content of an element.
+    ///
+    /// This uses the same rules as [`Escaper`].
+    Html,
+    /// A double-quoted attribute value.
+    ///
+    /// This uses the same rules as [`Escaper`].
+    Attribute,
+    /// The start of a URL-valued attribute, such as `href` or `src`.
+    ///
+    /// Characters that are not allowed in a URL are percent-encoded.
+    /// The result is then escaped as an attribute value.
+    ///
+    /// If the URL has a scheme that isn't in the context's allowlist,
+    /// such as `javascript:`, it is replaced with `about:invalid`. See
+    /// [`Context::with_url_schemes`].
+    Url,
+    /// The middle of a URL-valued attribute, after some other text.
+    ///
+    /// This is escaped as for [`Position::Url`], except that `:` is
+    /// percent-encoded too, so that the value can't complete a scheme.
+    /// This is skipped if the context allows any scheme.
+    UrlPath,
+    /// The query string or fragment of a URL-valued attribute.
+    ///
+    /// All characters except ASCII letters, digits, and `-._~` are
+    /// percent-encoded.
+    UrlComponent,
+    /// The inside of a JavaScript string literal, either in a `script`
+    /// element or an event handler attribute.
+    ///
+    /// Quotes, backslashes, `$`, HTML special characters, and line
+    /// terminators are written as JavaScript escape sequences, so the
+    /// value is also safe in a template literal.
+    JsString,
+    /// A JavaScript expression, outside of any string literal.
+    ///
+    /// The value is escaped as for [`Position::JsString`] and wrapped in
+    /// single quotes, so that it is treated as a string. Numbers and
+    /// booleans are written as-is if the context allows it; see
+    /// [`Context::as_js_literal`].
+    JsValue,
+    /// A CSS property value, either in a `style` element or a `style`
+    /// attribute.
+    ///
+    /// This uses the same rules as [`CssEscaper`].
+    Css,
+}
+
+/// Describes where a value is inserted, and how it should be escaped.
+///
+/// This combines a [`Position`] in the document with the [`Profile`]
+/// that HTML escaping should follow, and the URL schemes that are
+/// allowed in URL-valued attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Context {
+    position: Position,
+    profile: Profile,
+    url_schemes: Option<&'static [&'static str]>,
+    attribute: bool,
+    token_list: bool,
+    js_literal: bool,
+    charset: Charset,
+}
+
+impl Context {
+    /// The URL schemes that are allowed by default.
+    ///
+    /// URLs without a scheme, such as `/about` or `#top`, are always
+    /// allowed.
+    pub const DEFAULT_URL_SCHEMES: &'static [&'static str] = &["http", "https", "mailto", "tel"];
+
+    /// Creates a `Context` for the given position, with the default
+    /// profile, URL schemes and charset.
+    pub const fn new(position: Position) -> Context {
+        Context {
+            position,
+            profile: Profile::Minimal,
+            url_schemes: Some(Context::DEFAULT_URL_SCHEMES),
+            attribute: matches!(
+                position,
+                Position::Attribute | Position::Url | Position::UrlPath | Position::UrlComponent
+            ),
+            token_list: false,
+            js_literal: false,
+            charset: Charset::UTF8,
+        }
+    }
+
+    /// Returns a copy of this context at the given position, keeping
+    /// the other settings.
+    pub const fn with_position(self, position: Position) -> Context {
+        Context { position, ..self }
+    }
+
+    /// Returns a copy of this context with the given profile.
+    pub const fn with_profile(self, profile: Profile) -> Context {
+        Context { profile, ..self }
+    }
+
+    /// Returns a copy of this context with the given charset.
+    pub const fn with_charset(self, charset: Charset) -> Context {
+        Context { charset, ..self }
+    }
+
+    /// Returns a copy of this context that allows the given URL schemes.
+    ///
+    /// The schemes should be in lowercase, without the trailing `:`.
+    pub const fn with_url_schemes(self, schemes: &'static [&'static str]) -> Context {
+        Context {
+            url_schemes: Some(schemes),
+            ..self
+        }
+    }
+
+    /// Returns a copy of this context that allows URLs with any scheme.
+    ///
+    /// Only use this for URLs that come from a trusted source.
+    pub const fn with_any_url_scheme(self) -> Context {
+        Context {
+            url_schemes: None,
+            ..self
+        }
+    }
+
+    /// Returns a copy of this context that is marked as being inside
+    /// an attribute value.
+    ///
+    /// This only needs to be called for positions that can appear both
+    /// inside and outside of attributes, such as [`Position::JsValue`]
+    /// in an event handler attribute.
+    pub const fn in_attribute(self) -> Context {
+        Context {
+            attribute: true,
+            ..self
+        }
+    }
+
+    /// Returns a copy of this context that is marked as being inside
+    /// an attribute that holds a space-separated list of tokens, such as
+    /// `class` or `rel`.
+    ///
+    /// This doesn't change how text is escaped. It tells collections
+    /// to put a space between their items.
+    pub const fn in_token_list(self) -> Context {
+        Context {
+            token_list: true,
+            ..self
+        }
+    }
+
+    /// Returns a copy of this context that writes numbers and booleans
+    /// in [`Position::JsValue`] without quotes.
+    ///
+    /// Only use this for types that always represent a number or a
+    /// boolean, such as `i32` or `bool`. Strings should stay strings in
+    /// JavaScript, even if their contents look like a number.
+    pub const fn as_js_literal(self) -> Context {
+        Context {
+            js_literal: true,
+            ..self
+        }
+    }
+
+    /// Returns the position in the document.
+    pub fn position(self) -> Position {
+        self.position
+    }
+
+    /// Returns the profile that HTML escaping follows.
+    pub fn profile(self) -> Profile {
+        self.profile
+    }
+
+    /// Returns the charset that the output follows.
+    pub fn charset(self) -> Charset {
+        self.charset
+    }
+
+    /// Returns whether the value is inside an attribute value.
+    ///
+    /// Text inside an attribute is decoded by the browser, unlike the
+    /// contents of a `script` or `style` element. Types that write
+    /// trusted output may need to HTML-escape it in this case.
+    pub fn is_attribute(self) -> bool {
+        self.attribute
+    }
+
+    /// Returns whether the value is inside an attribute that holds a
+    /// space-separated list of tokens.
+    pub fn is_token_list(self) -> bool {
+        self.token_list
+    }
+
+    /// Returns whether numbers and booleans are written without quotes
+    /// in [`Position::JsValue`].
+    pub fn is_js_literal(self) -> bool {
+        self.js_literal
+    }
+
+    /// Returns the URL schemes that are allowed, or `None` if any
+    /// scheme is allowed.
+    pub fn url_schemes(self) -> Option<&'static [&'static str]> {
+        self.url_schemes
+    }
+
+    /// Returns whether the given URL has a scheme that is allowed, or
+    /// no scheme at all.
+    ///
+    /// The URL should already be escaped as for [`Position::Url`], so
+    /// that whitespace and control characters can't hide a scheme.
+    pub fn allows_url(self, url: &str) -> bool {
+        match (self.url_schemes, url_scheme(url)) {
+            (Some(schemes), Some(scheme)) => schemes.iter().any(|s| s.eq_ignore_ascii_case(scheme)),
+            _ => true,
+        }
+    }
+}
+
+impl Default for Context {
+    fn default() -> Context {
+        Context::new(Position::Html)
+    }
+}
+
+impl From<Position> for Context {
+    fn from(position: Position) -> Context {
+        Context::new(position)
+    }
+}
+
+/// An adapter that escapes text for a particular [`Context`].
+///
+/// For [`Position::Html`] and [`Position::Attribute`], this behaves
+/// exactly like [`Escaper`].
+///
+/// Call [`.finish()`](ContextEscaper::finish) after writing the value.
+/// This is needed to close the quotes added by [`Position::JsValue`],
+/// and to check the scheme of a [`Position::Url`].
+///
+/// # Example
+///
+/// ```rust
+/// use maud_htmlescape::{ContextEscaper, Position};
+/// use std::fmt::Write;
+/// let mut s = String::new();
+/// let mut escaper = ContextEscaper::new(Position::JsValue.into(), &mut s);
+/// write!(escaper, "</script>").unwrap();
+/// escaper.finish();
+/// assert_eq!(s, r"'\u003C\/script\u003E'");
+/// ```
+pub struct ContextEscaper<'a> {
+    buffer: &'a mut String,
+    context: Context,
+    start: usize,
+}
+
+impl<'a> ContextEscaper<'a> {
+    /// Creates a `ContextEscaper` from a `String`.
+    pub fn new(context: Context, buffer: &'a mut String) -> ContextEscaper<'a> {
+        if context.position == Position::JsValue {
+            buffer.push('\'');
+        }
+        let start = buffer.len();
+        ContextEscaper {
+            buffer,
+            context,
+            start,
+        }
+    }
+
+    /// Finishes writing the value.
+    pub fn finish(self) {
+        match self.context.position {
+            Position::JsValue => {
+                if self.context.js_literal && is_js_literal(&self.buffer[self.start..]) {
+                    // Remove the opening quote
+                    self.buffer.remove(self.start - 1);
+                } else {
+                    self.buffer.push('\'');
+                }
+            }
+            Position::Url if !self.context.allows_url(&self.buffer[self.start..]) => {
+                self.buffer.truncate(self.start);
+                self.buffer.push_str("about:invalid");
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a> fmt::Write for ContextEscaper<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let Context {
+            profile, charset, ..
+        } = self.context;
+        let filtered = charset.filter_str(s);
+        match self.context.position {
+            Position::Html | Position::Attribute => {
+                Escaper::with_profile(&mut *self.buffer, profile)
+                    .with_charset(charset)
+                    .write_str(s)
+            }
+            Position::Url | Position::UrlPath => {
+                // Stop the value from completing a scheme that was
+                // started by the text before it
+                let escape_colon = self.context.position == Position::UrlPath
+                    && self.context.url_schemes.is_some();
+                for b in filtered.bytes() {
+                    match b {
+                        b':' if escape_colon => push_percent_encoded(self.buffer, b),
+                        b'%'
+                        | b'!'
+                        | b'#'
+                        | b'$'
+                        | b'&'
+                        | b'\''
+                        | b'('..=b';'
+                        | b'='
+                        | b'?'
+                        | b'@'
+                        | b'A'..=b'Z'
+                        | b'['
+                        | b']'
+                        | b'_'
+                        | b'a'..=b'z'
+                        | b'~' => match profile.escape_byte(b) {
+                            Some(escaped) => self.buffer.push_str(escaped),
+                            None => self.buffer.push(b as char),
+                        },
+                        _ => push_percent_encoded(self.buffer, b),
+                    }
+                }
+                Ok(())
+            }
+            Position::UrlComponent => {
+                for b in filtered.bytes() {
+                    match b {
+                        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                            self.buffer.push(b as char)
+                        }
+                        _ => push_percent_encoded(self.buffer, b),
+                    }
+                }
+                Ok(())
+            }
+            Position::JsString | Position::JsValue => {
+                for c in filtered.chars() {
+                    match c {
+                        '\\' => self.buffer.push_str("\\\\"),
+                        '/' => self.buffer.push_str("\\/"),
+                        '\n' => self.buffer.push_str("\\n"),
+                        '\r' => self.buffer.push_str("\\r"),
+                        '\t' => self.buffer.push_str("\\t"),
+                        // `$` could start a `${...}` in a template literal
+                        '"' | '\'' | '`' | '$' | '&' | '<' | '>' | '=' | '\u{2028}'
+                        | '\u{2029}' => write!(self.buffer, "\\u{:04X}", c as u32)?,
+                        c if c.is_ascii_control() => write!(self.buffer, "\\u{:04X}", c as u32)?,
+                        c if !c.is_ascii() && charset.is_ascii_only() => {
+                            for unit in c.encode_utf16(&mut [0; 2]) {
+                                write!(self.buffer, "\\u{:04X}", unit)?;
+                            }
+                        }
+                        c => self.buffer.push(c),
+                    }
+                }
+                Ok(())
+            }
+            Position::Css => CssEscaper::new(&mut *self.buffer)
+                .with_charset(charset)
+                .write_str(s),
+        }
+    }
+}
+
+fn push_percent_encoded(buffer: &mut String, b: u8) {
+    const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+    buffer.push('%');
+    buffer.push(HEX_DIGITS[(b >> 4) as usize] as char);
+    buffer.push(HEX_DIGITS[(b & 0xf) as usize] as char);
+}
+
+/// Returns the scheme of the given URL, or `None` if it is relative.
+///
+/// The URL should already be percent-encoded, so that whitespace and
+/// control characters can't hide a scheme.
+fn url_scheme(url: &str) -> Option<&str> {
+    let end = url.find([':', '/', '?', '#'])?;
+    let scheme = &url[..end];
+    let valid = url[end..].starts_with(':')
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'-' || b == b'.');
+    if valid {
+        Some(scheme)
+    } else {
+        None
+    }
+}
+
+/// Returns whether the given text is a JavaScript number or boolean
+/// literal, and can therefore be written without quotes.
+fn is_js_literal(s: &str) -> bool {
+    if s == "true" || s == "false" {
+        return true;
+    }
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let (mantissa, exponent) = match digits.find(['e', 'E']) {
+        Some(i) => (&digits[..i], Some(&digits[i + 1..])),
+        None => (digits, None),
+    };
+    let (integer, fraction) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], Some(&mantissa[i + 1..])),
+        None => (mantissa, None),
+    };
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    all_digits(integer)
+        && fraction.is_none_or(all_digits)
+        && exponent.is_none_or(|e| all_digits(e.strip_prefix(['+', '-']).unwrap_or(e)))
 }
 
 #[cfg(test)]
 mod test {
-    use crate::Escaper;
+    use crate::{
+        Charset, Context, ContextEscaper, Escaper, InvalidChars, IoEscaper, Position, Profile,
+    };
     use std::fmt::Write;
 
     #[test]
@@ -65,4 +972,275 @@ mod test {
         write!(Escaper::new(&mut s), "<script>launchMissiles()</script>").unwrap();
         assert_eq!(s, "&lt;script&gt;launchMissiles()&lt;/script&gt;");
     }
+
+    #[test]
+    fn matches_naive_escaping() {
+        fn naive(input: &str) -> String {
+            let mut s = String::new();
+            for c in input.chars() {
+                match c {
+                    '&' => s.push_str("&amp;"),
+                    '<' => s.push_str("&lt;"),
+                    '>' => s.push_str("&gt;"),
+                    '"' => s.push_str("&quot;"),
+                    c => s.push(c),
+                }
+            }
+            s
+        }
+        let pieces = [
+            "a",
+            "&",
+            "<",
+            ">",
+            "\"",
+            "'",
+            "ö",
+            "\u{1F600}",
+            "\0",
+            "\u{ff}",
+        ];
+        // Cover every special byte at every offset within a word
+        for len in 0..40 {
+            for (k, piece) in pieces.iter().enumerate() {
+                let input: String = (0..len)
+                    .map(|i| if i % 7 == k % 7 { *piece } else { "x" })
+                    .collect();
+                let mut s = String::new();
+                Escaper::new(&mut s).write_str(&input).unwrap();
+                assert_eq!(s, naive(&input), "input: {:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn other_targets() {
+        use crate::{FmtEscaper, IoEscaper, VecEscaper};
+        use std::io::Write as _;
+
+        let input = "<p class=\"x\">Fish & chips, ö</p>";
+        let expected = "&lt;p class=&quot;x&quot;&gt;Fish &amp; chips, ö&lt;/p&gt;";
+
+        let mut v = Vec::new();
+        VecEscaper::new(&mut v).write_str(input).unwrap();
+        assert_eq!(v, expected.as_bytes());
+
+        let mut s = String::new();
+        write!(FmtEscaper::new(&mut s), "{}", input).unwrap();
+        assert_eq!(s, expected);
+
+        let mut escaper = IoEscaper::new(Vec::new());
+        escaper.write_all(input.as_bytes()).unwrap();
+        escaper.write_all(b"\xff<").unwrap();
+        let mut expected = expected.as_bytes().to_vec();
+        expected.extend_from_slice(b"\xff&lt;");
+        assert_eq!(escaper.into_inner(), expected);
+    }
+
+    #[test]
+    fn io_escaper_partial_writes() {
+        use std::io::{self, Write as _};
+
+        /// Accepts at most three bytes at a time, and fails every other
+        /// call.
+        struct Flaky {
+            out: Vec<u8>,
+            fail: bool,
+        }
+
+        impl io::Write for Flaky {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.fail = !self.fail;
+                if self.fail {
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+                let n = buf.len().min(3);
+                self.out.extend_from_slice(&buf[..n]);
+                Ok(n)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut escaper = IoEscaper::new(Flaky {
+            out: Vec::new(),
+            fail: false,
+        });
+        assert!(escaper.write(b"Fish & chips").is_err());
+        assert_eq!(escaper.write(b"Fish & chips").unwrap(), 3);
+        escaper.write_all(b"h & chips <3").unwrap();
+        assert_eq!(escaper.into_inner().out, b"Fish &amp; chips &lt;3");
+    }
+
+    fn escape(position: Position, input: &str) -> String {
+        escape_in(Context::new(position), input)
+    }
+
+    fn escape_in(context: Context, input: &str) -> String {
+        let mut s = String::new();
+        let mut escaper = ContextEscaper::new(context, &mut s);
+        escaper.write_str(input).unwrap();
+        escaper.finish();
+        s
+    }
+
+    #[test]
+    fn contexts() {
+        assert_eq!(
+            escape(Position::Html, "<a href=\"\">"),
+            "&lt;a href=&quot;&quot;&gt;"
+        );
+        assert_eq!(
+            escape(Position::Url, "/search?q=a b&c=\"ö\""),
+            "/search?q=a%20b&amp;c=%22%C3%B6%22"
+        );
+        assert_eq!(escape(Position::UrlPath, "a:b c"), "a%3Ab%20c");
+        assert_eq!(escape(Position::UrlComponent, "a b&c/d"), "a%20b%26c%2Fd");
+        assert_eq!(
+            escape(Position::JsString, "'</script>\n"),
+            r"\u0027\u003C\/script\u003E\n"
+        );
+        assert_eq!(
+            escape(Position::JsString, "${alert(1)}"),
+            r"\u0024{alert(1)}"
+        );
+        assert_eq!(
+            escape(Position::Css, "red; x:url(y)"),
+            r"red\3b  x\3a url\28 y\29 "
+        );
+        assert_eq!(
+            escape(Position::Css, "1px solid #f00, 50% -2.5em"),
+            "1px solid #f00, 50% -2.5em"
+        );
+        assert_eq!(
+            escape(Position::Css, "/*\"</style>\\"),
+            r"\2f \2a \22 \3c \2f style\3e \5c "
+        );
+    }
+
+    #[test]
+    fn charsets() {
+        let input = "<caf\u{e9}> \u{1F600}\u{0}\u{7}\t\u{85}\u{FFFE}";
+        let escape = |charset| {
+            let mut s = String::new();
+            Escaper::new(&mut s)
+                .with_charset(charset)
+                .write_str(input)
+                .unwrap();
+            s
+        };
+        assert_eq!(
+            escape(Charset::ASCII),
+            "&lt;caf&#xE9;&gt; &#x1F600;\u{0}\u{7}\t&#x85;&#xFFFE;"
+        );
+        assert_eq!(
+            escape(Charset::UTF8.with_invalid_chars(InvalidChars::Strip)),
+            "&lt;caf\u{e9}&gt; \u{1F600}\t"
+        );
+        assert_eq!(
+            escape(Charset::ASCII.with_invalid_chars(InvalidChars::Replace)),
+            "&lt;caf&#xE9;&gt; &#x1F600;&#xFFFD;&#xFFFD;\t&#xFFFD;&#xFFFD;"
+        );
+        let context = Context::new(Position::JsString)
+            .with_charset(Charset::ASCII.with_invalid_chars(InvalidChars::Strip));
+        assert_eq!(
+            escape_in(context, "\u{e9}\u{1F600}\u{1}"),
+            r"\u00E9\uD83D\uDE00"
+        );
+        let context = Context::new(Position::Css).with_charset(Charset::ASCII);
+        assert_eq!(escape_in(context, "caf\u{e9}"), r"caf\e9 ");
+        let context = Context::new(Position::Url)
+            .with_charset(Charset::UTF8.with_invalid_chars(InvalidChars::Strip));
+        assert_eq!(escape_in(context, "/a\u{1}b"), "/ab");
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        use std::io::Write;
+        let escape = |charset| {
+            let mut escaper = IoEscaper::new(Vec::new()).with_charset(charset);
+            escaper.write_all(b"a\xff<").unwrap();
+            escaper.into_inner()
+        };
+        assert_eq!(escape(Charset::UTF8), b"a\xff&lt;");
+        assert_eq!(escape(Charset::ASCII), b"a&#xFFFD;&lt;");
+        assert_eq!(
+            escape(Charset::UTF8.with_invalid_chars(InvalidChars::Strip)),
+            b"a&lt;"
+        );
+    }
+
+    #[test]
+    fn profiles() {
+        let input = r#"<a title='x' data-x=`y`>&"#;
+        let escape = |profile| {
+            let mut s = String::new();
+            Escaper::with_profile(&mut s, profile)
+                .write_str(input)
+                .unwrap();
+            s
+        };
+        assert_eq!(
+            escape(Profile::Minimal),
+            "&lt;a title='x' data-x=`y`&gt;&amp;"
+        );
+        assert_eq!(
+            escape(Profile::Xml),
+            "&lt;a title=&#39;x&#39; data-x=`y`&gt;&amp;"
+        );
+        assert_eq!(
+            escape(Profile::Strict),
+            "&lt;a title&#61;&#39;x&#39; data-x&#61;&#96;y&#96;&gt;&amp;"
+        );
+        assert_eq!(
+            escape_in(
+                Context::new(Position::Url).with_profile(Profile::Strict),
+                "/?a='1'"
+            ),
+            "/?a&#61;&#39;1&#39;"
+        );
+    }
+
+    #[test]
+    fn url_schemes() {
+        for url in [
+            "/a:b",
+            "?a:b",
+            "#a:b",
+            "https://x/",
+            "MailTo:a@b",
+            "1a:b",
+            "%20x:y",
+        ] {
+            assert_eq!(escape(Position::Url, url), url);
+        }
+        for url in ["javascript:alert(1)", "JavaScript:x", "data:text/html,x"] {
+            assert_eq!(escape(Position::Url, url), "about:invalid");
+        }
+        // Whitespace inside the scheme is percent-encoded, which makes
+        // the URL relative
+        assert_eq!(escape(Position::Url, "java\nscript:x"), "java%0Ascript:x");
+        assert_eq!(escape(Position::Url, "\tjavascript:x"), "%09javascript:x");
+        let context = Context::new(Position::Url).with_url_schemes(&["data"]);
+        assert_eq!(escape_in(context, "data:,x"), "data:,x");
+        assert_eq!(escape_in(context, "https://x/"), "about:invalid");
+        let context = Context::new(Position::UrlPath).with_any_url_scheme();
+        assert_eq!(escape_in(context, "a:b"), "a:b");
+    }
+
+    #[test]
+    fn js_value_quotes_strings() {
+        assert_eq!(escape(Position::JsValue, "42"), "'42'");
+        assert_eq!(escape(Position::JsValue, "true"), "'true'");
+        assert_eq!(escape(Position::JsValue, "alert(1)"), "'alert(1)'");
+        assert_eq!(escape(Position::JsValue, ""), "''");
+        let context = Context::new(Position::JsValue).as_js_literal();
+        assert_eq!(escape_in(context, "42"), "42");
+        assert_eq!(escape_in(context, "-1.5e+3"), "-1.5e+3");
+        assert_eq!(escape_in(context, "true"), "true");
+        assert_eq!(escape_in(context, "1."), "'1.'");
+        assert_eq!(escape_in(context, "inf"), "'inf'");
+    }
 }
@@ -0,0 +1,208 @@
+/// Attributes whose value is a URL.
+///
+/// Values of these attributes are escaped with [`Position::Url`], so
+/// that only the allowed URL schemes get through.
+///
+/// [`Position::Url`]: crate::Position::Url
+pub const URL_ATTRIBUTES: &[&str] = &[
+    "action",
+    "background",
+    "cite",
+    "codebase",
+    "data",
+    "formaction",
+    "href",
+    "icon",
+    "longdesc",
+    "manifest",
+    "poster",
+    "src",
+    "usemap",
+    "xlink:href",
+];
+
+/// Returns whether the given string can be written as an attribute
+/// name.
+///
+/// This follows the HTML syntax: a name is one or more characters,
+/// other than whitespace, control characters, noncharacters, and the
+/// characters `"`, `'`, `<`, `>`, `/` and `=`. The character `&` is
+/// rejected too, so that names can be written without escaping.
+pub fn is_valid_attribute_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| {
+            !matches!(c, ' ' | '"' | '\'' | '<' | '>' | '/' | '=' | '&')
+                && !c.is_control()
+                && !is_noncharacter(c)
+        })
+}
+
+/// Prefixes of attributes that Alpine.js, Vue and htmx run as event
+/// handlers or JavaScript expressions.
+///
+/// Every `x-` and `v-` directive counts, except for those listed in
+/// [`INERT_DIRECTIVES`].
+const SCRIPT_ATTRIBUTE_PREFIXES: &[&str] = &["@", ":", "x-", "v-", "hx-on"];
+
+/// Alpine.js and Vue directives whose values aren't run as JavaScript.
+const INERT_DIRECTIVES: &[&str] = &[
+    "x-cloak",
+    "x-ignore",
+    "x-ref",
+    "x-teleport",
+    "x-transition",
+    "v-cloak",
+    "v-pre",
+];
+
+/// Returns whether the value of the given attribute is run as
+/// JavaScript, as for event handlers such as `onclick`.
+///
+/// This also covers the directives of common frameworks, such as
+/// `@click`, `:class`, `x-data`, `v-if` and `hx-on::after-request`. The
+/// name should be in lowercase.
+pub fn is_script_attribute(name: &str) -> bool {
+    let is_inert = INERT_DIRECTIVES.iter().any(|directive| {
+        name.strip_prefix(directive)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with([':', '.']))
+    });
+    name.starts_with("on")
+        || (SCRIPT_ATTRIBUTE_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+            && !is_inert)
+}
+
+/// Attributes whose value is a space-separated list of tokens.
+///
+/// When a collection is spliced into one of these attributes, its
+/// items are separated by spaces. See [`Context::in_token_list`].
+///
+/// [`Context::in_token_list`]: crate::Context::in_token_list
+pub const TOKEN_LIST_ATTRIBUTES: &[&str] = &[
+    "accesskey",
+    "aria-controls",
+    "aria-describedby",
+    "aria-flowto",
+    "aria-labelledby",
+    "aria-owns",
+    "autocomplete",
+    "blocking",
+    "class",
+    "headers",
+    "itemprop",
+    "itemref",
+    "itemtype",
+    "ping",
+    "rel",
+    "rev",
+    "sandbox",
+];
+
+/// Elements that have no end tag.
+pub const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Returns whether the given string can be written as an element name.
+///
+/// This is stricter than the HTML syntax: a name must start with an
+/// ASCII letter, followed by ASCII letters, digits, and the characters
+/// `-`, `_`, `.` and `:`.
+pub fn is_valid_element_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// Returns whether the given character is a Unicode noncharacter, such
+/// as `U+FFFE`.
+pub(crate) fn is_noncharacter(c: char) -> bool {
+    matches!(c, '\u{fdd0}'..='\u{fdef}') || c as u32 & 0xfffe == 0xfffe
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn attribute_names() {
+        for name in [
+            "href",
+            "data-id",
+            "aria-label",
+            "x-on:click",
+            "@click",
+            ":class",
+            "é",
+        ] {
+            assert!(is_valid_attribute_name(name), "{:?}", name);
+        }
+        for name in [
+            "",
+            "a b",
+            "a\"",
+            "a'",
+            "a>",
+            "a/",
+            "a=b",
+            "a&b",
+            "<a",
+            "a\0",
+            "a\u{fffe}",
+        ] {
+            assert!(!is_valid_attribute_name(name), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn script_attributes() {
+        for name in [
+            "onclick",
+            "@click.prevent",
+            ":class",
+            "x-on:click",
+            "hx-on::after-request",
+            "x-data",
+            "x-init",
+            "x-show",
+            "x-text",
+            "x-html",
+            "x-effect",
+            "x-model.number",
+            "x-if",
+            "x-for",
+            "v-if",
+            "v-show",
+            "v-html",
+            "v-model",
+            "v-for",
+        ] {
+            assert!(is_script_attribute(name), "{:?}", name);
+        }
+        for name in [
+            "title",
+            "x-cloak",
+            "x-ignore",
+            "x-ref",
+            "x-transition:enter",
+            "v-cloak",
+            "v-pre",
+            "data-on",
+            "hx-get",
+        ] {
+            assert!(!is_script_attribute(name), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn element_names() {
+        for name in ["h1", "DIV", "my-element", "svg:rect", "x_y.z"] {
+            assert!(is_valid_element_name(name), "{:?}", name);
+        }
+        for name in ["", "1h", "-a", "a b", "a>", "a/", "é", "a\"", "<a"] {
+            assert!(!is_valid_element_name(name), "{:?}", name);
+        }
+    }
+}
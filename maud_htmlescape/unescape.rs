@@ -0,0 +1,217 @@
+use std::borrow::Cow;
+use std::char;
+
+use crate::entities::ENTITIES;
+
+/// Decodes character references in HTML text, such as `&amp;`,
+/// `&#x27;` and `&nbsp;`.
+///
+/// This follows the rules that browsers use for the text content of
+/// an element, as described in the [HTML5 specification]:
+///
+/// * Every named reference in HTML5 is recognized. For the legacy
+///   names that allow it, such as `&amp`, the trailing `;` is optional.
+/// * Numeric references may be decimal (`&#39;`) or hexadecimal
+///   (`&#x27;`). References to zero, surrogates, or values above
+///   `U+10FFFF` decode to `U+FFFD`, and references to `0x80`–`0x9F`
+///   are read as Windows-1252.
+/// * Anything that isn't a valid reference, such as `&foo;` or a lone
+///   `&`, is kept as-is.
+///
+/// If the input contains no references, it is returned without
+/// allocating.
+///
+/// [HTML5 specification]: https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+///
+/// # Example
+///
+/// ```rust
+/// use maud_htmlescape::unescape;
+/// assert_eq!(unescape("Fish &amp; chips&nbsp;&#x2014; &lt;3"), "Fish & chips\u{a0}\u{2014} <3");
+/// ```
+pub fn unescape(s: &str) -> Cow<'_, str> {
+    unescape_impl(s, false)
+}
+
+/// Decodes character references in an HTML attribute value.
+///
+/// This is the same as [`unescape`], except that named references
+/// without a trailing `;` are left alone when followed by `=` or a
+/// letter or digit. This matches how browsers read URLs such as
+/// `?a=1&copy=2`.
+pub fn unescape_attribute(s: &str) -> Cow<'_, str> {
+    unescape_impl(s, true)
+}
+
+fn unescape_impl(s: &str, in_attribute: bool) -> Cow<'_, str> {
+    let mut rest = match s.find('&') {
+        Some(i) => i,
+        None => return Cow::Borrowed(s),
+    };
+    let mut result = String::with_capacity(s.len());
+    result.push_str(&s[..rest]);
+    while let Some(i) = s[rest..].find('&') {
+        let amp = rest + i;
+        result.push_str(&s[rest..amp]);
+        let after = &s[amp + 1..];
+        let consumed = match after.strip_prefix('#') {
+            Some(number) => numeric_reference(number, &mut result).map(|len| len + 1),
+            None => named_reference(after, in_attribute, &mut result),
+        };
+        match consumed {
+            Some(len) => rest = amp + 1 + len,
+            None => {
+                result.push('&');
+                rest = amp + 1;
+            }
+        }
+    }
+    result.push_str(&s[rest..]);
+    Cow::Owned(result)
+}
+
+/// Decodes a numeric reference, given the text after the `&#`.
+///
+/// Returns the number of bytes consumed, or `None` if this is not a
+/// valid reference.
+fn numeric_reference(s: &str, result: &mut String) -> Option<usize> {
+    let (radix, prefix_len) = match s.as_bytes().first() {
+        Some(b'x') | Some(b'X') => (16, 1),
+        _ => (10, 0),
+    };
+    let digits_len = s[prefix_len..]
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(s.len() - prefix_len);
+    if digits_len == 0 {
+        return None;
+    }
+    let digits = &s[prefix_len..prefix_len + digits_len];
+    // Saturate on overflow, since the result is out of range anyway
+    let code = u32::from_str_radix(digits, radix).unwrap_or(u32::MAX);
+    result.push(decode_code_point(code));
+    let mut len = prefix_len + digits_len;
+    if s[len..].starts_with(';') {
+        len += 1;
+    }
+    Some(len)
+}
+
+/// Maps a numeric reference to the character it represents.
+fn decode_code_point(code: u32) -> char {
+    match code {
+        0x80..=0x9f => WINDOWS_1252[(code - 0x80) as usize],
+        0 => char::REPLACEMENT_CHARACTER,
+        code => char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER),
+    }
+}
+
+/// Characters that `&#x80;` through `&#x9F;` are replaced with.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+/// The length of the longest entity name, including the `;`.
+const MAX_NAME_LEN: usize = 32;
+
+/// Decodes a named reference, given the text after the `&`.
+///
+/// Returns the number of bytes consumed, or `None` if this is not a
+/// valid reference.
+fn named_reference(s: &str, in_attribute: bool, result: &mut String) -> Option<usize> {
+    let name_len = s
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(s.len());
+    if name_len == 0 {
+        return None;
+    }
+    let with_semicolon = s[name_len..].starts_with(';');
+    let max_len = (name_len + with_semicolon as usize).min(MAX_NAME_LEN);
+    // Find the longest name that matches
+    let (len, characters) = (1..=max_len)
+        .rev()
+        .find_map(|len| lookup(&s[..len]).map(|characters| (len, characters)))?;
+    if in_attribute && !s[..len].ends_with(';') {
+        if let Some(next) = s[len..].chars().next() {
+            if next == '=' || next.is_ascii_alphanumeric() {
+                return None;
+            }
+        }
+    }
+    result.push_str(characters);
+    Some(len)
+}
+
+fn lookup(name: &str) -> Option<&'static str> {
+    ENTITIES
+        .binary_search_by(|&(entity, _)| entity.cmp(name))
+        .ok()
+        .map(|i| ENTITIES[i].1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{unescape, unescape_attribute};
+    use crate::entities::ENTITIES;
+    use crate::Escaper;
+    use std::borrow::Cow;
+    use std::fmt::Write;
+
+    #[test]
+    fn named_references() {
+        assert_eq!(unescape("&amp;&lt;&gt;&quot;&apos;"), "&<>\"'");
+        assert_eq!(unescape("a&nbsp;b"), "a\u{a0}b");
+        assert_eq!(unescape("&NotNestedGreaterGreater;"), "\u{2AA2}\u{338}");
+        // Legacy names without a semicolon
+        assert_eq!(unescape("&amp &copy2021"), "& \u{a9}2021");
+        // Longest match wins
+        assert_eq!(unescape("&notin; &notit;"), "\u{2209} \u{ac}it;");
+        // Unknown names are kept
+        assert_eq!(unescape("&pinkie; & &;"), "&pinkie; & &;");
+    }
+
+    #[test]
+    fn numeric_references() {
+        assert_eq!(unescape("&#39;&#x27;&#X27;&#0039"), "''''");
+        assert_eq!(unescape("&#x1F600;"), "\u{1F600}");
+        assert_eq!(
+            unescape("&#0;&#xD800;&#x110000;&#99999999999;"),
+            "\u{FFFD}".repeat(4)
+        );
+        assert_eq!(unescape("&#x80;&#150;"), "\u{20AC}\u{2013}");
+        assert_eq!(unescape("&#; &#x; &#xyz;"), "&#; &#x; &#xyz;");
+    }
+
+    #[test]
+    fn attributes() {
+        assert_eq!(unescape_attribute("?a=1&copy=2&amp=3"), "?a=1&copy=2&amp=3");
+        assert_eq!(
+            unescape_attribute("&copy;=&copy &amp;b"),
+            "\u{a9}=\u{a9} &b"
+        );
+        assert_eq!(unescape("?a=1&copy=2"), "?a=1\u{a9}=2");
+    }
+
+    #[test]
+    fn borrows_when_unchanged() {
+        assert!(matches!(unescape("no references"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = "<a href=\"/?a=1&b=2\">Fish & chips &amp; ö</a>";
+        let mut escaped = String::new();
+        Escaper::new(&mut escaped).write_str(input).unwrap();
+        assert_eq!(unescape(&escaped), input);
+    }
+
+    #[test]
+    fn entities_are_sorted() {
+        assert!(ENTITIES.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(ENTITIES
+            .iter()
+            .all(|(name, _)| name.len() <= super::MAX_NAME_LEN));
+    }
+}
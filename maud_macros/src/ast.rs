@@ -1,5 +1,20 @@
+use maud_htmlescape::{Charset, Profile};
 use proc_macro2::{TokenStream, TokenTree};
 use proc_macro_error::SpanRange;
+use syn::Lit;
+
+/// Settings for a whole `html!` invocation, given as inner attributes
+/// such as `#![escape = "xml"]`.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub profile: Profile,
+    pub charset: Charset,
+    /// The URL schemes to allow, if not the default ones.
+    pub url_schemes: Option<Vec<String>>,
+    /// Whether to indent the output. This is set by `html_pretty!`
+    /// rather than an inner attribute.
+    pub pretty: bool,
+}
 
 #[derive(Debug)]
 pub enum Markup {
@@ -24,6 +39,15 @@ pub enum Markup {
         attrs: Vec<Attr>,
         body: ElementBody,
     },
+    /// An element whose name is computed at runtime, as in
+    /// `@element(tag)`.
+    DynamicElement {
+        at_span: SpanRange,
+        name: TokenStream,
+        name_span: SpanRange,
+        attrs: Vec<Attr>,
+        body: ElementBody,
+    },
     Let {
         at_span: SpanRange,
         tokens: TokenStream,
@@ -53,6 +77,9 @@ impl Markup {
                 let name_span = span_tokens(name.clone());
                 name_span.join_range(body.span())
             }
+            Markup::DynamicElement {
+                at_span, ref body, ..
+            } => at_span.join_range(body.span()),
             Markup::Let {
                 at_span,
                 ref tokens,
@@ -79,9 +106,28 @@ pub enum Attr {
     Attribute {
         attribute: Attribute,
     },
+    Spread {
+        spread: Spread,
+    },
 }
 
 impl Attr {
+    /// Returns whether this is merged into the `class` attribute, either
+    /// as a `.foo` shorthand or as a `class=` value.
+    pub fn is_class(&self) -> bool {
+        match self {
+            Attr::Class { .. } => true,
+            Attr::Attribute {
+                attribute:
+                    Attribute {
+                        name,
+                        attr_type: AttrType::Normal { .. } | AttrType::Optional { .. },
+                    },
+            } => name_to_string(name.clone()) == "class",
+            _ => false,
+        }
+    }
+
     pub fn span(&self) -> SpanRange {
         match *self {
             Attr::Class {
@@ -105,6 +151,7 @@ impl Attr {
                 hash_span.join_range(name_span)
             }
             Attr::Attribute { ref attribute } => attribute.span(),
+            Attr::Spread { ref spread } => spread.span(),
         }
     }
 }
@@ -148,6 +195,23 @@ impl Special {
         let body_span = self.body.span();
         self.at_span.join_range(body_span)
     }
+
+    /// Returns whether this is a `@for` or `@while` loop.
+    pub fn is_loop(&self) -> bool {
+        matches!(
+            self.head.clone().into_iter().next(),
+            Some(TokenTree::Ident(keyword)) if keyword == "for" || keyword == "while"
+        )
+    }
+
+    /// Returns whether this is a plain `@else`, without an `if`.
+    pub fn is_else(&self) -> bool {
+        let mut head = self.head.clone().into_iter();
+        matches!(
+            (head.next(), head.next()),
+            (Some(TokenTree::Ident(keyword)), None) if keyword == "else"
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -169,19 +233,55 @@ impl Attribute {
 
 #[derive(Debug)]
 pub enum AttrType {
-    Normal { value: Markup },
-    Empty { toggler: Option<Toggler> },
+    Normal {
+        value: Markup,
+    },
+    /// An attribute that is only written when the value is `Some`, as
+    /// in `title=[value]`.
+    Optional {
+        toggler: Toggler,
+    },
+    Empty {
+        toggler: Option<Toggler>,
+    },
+    /// Values from several sources, such as the `.foo` shorthands and
+    /// `class=` values that are merged into one `class` attribute.
+    ///
+    /// The values are separated by spaces, and the attribute is left out
+    /// if all of them are empty. This is only made when desugaring.
+    Merged {
+        values: Vec<Markup>,
+    },
 }
 
 impl AttrType {
     fn span(&self) -> Option<SpanRange> {
         match *self {
             AttrType::Normal { ref value } => Some(value.span()),
+            AttrType::Optional { ref toggler } => Some(toggler.span()),
             AttrType::Empty { ref toggler } => toggler.as_ref().map(Toggler::span),
+            AttrType::Merged { ref values } => values
+                .iter()
+                .map(Markup::span)
+                .reduce(|first, last| first.join_range(last)),
         }
     }
 }
 
+/// A spread of attributes computed at runtime, as in `..(attrs)`.
+#[derive(Debug)]
+pub struct Spread {
+    pub dots_span: SpanRange,
+    pub expr: TokenStream,
+    pub outer_span: SpanRange,
+}
+
+impl Spread {
+    pub fn span(&self) -> SpanRange {
+        self.dots_span.join_range(self.outer_span)
+    }
+}
+
 #[derive(Debug)]
 pub struct Toggler {
     pub cond: TokenStream,
@@ -215,5 +315,14 @@ pub fn join_ranges<I: IntoIterator<Item = SpanRange>>(ranges: I) -> SpanRange {
 }
 
 pub fn name_to_string(name: TokenStream) -> String {
-    name.into_iter().map(|token| token.to_string()).collect()
+    name.into_iter()
+        .map(|token| match token {
+            // A quoted name, as in `"@click"="open = true"`
+            TokenTree::Literal(literal) => match Lit::new(literal.clone()) {
+                Lit::Str(lit_str) => lit_str.value(),
+                _ => literal.to_string(),
+            },
+            token => token.to_string(),
+        })
+        .collect()
 }
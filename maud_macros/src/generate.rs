@@ -1,36 +1,151 @@
-use maud_htmlescape::Escaper;
+use maud_htmlescape::{
+    is_script_attribute, Charset, Escaper, InvalidChars, Profile, TOKEN_LIST_ATTRIBUTES,
+    URL_ATTRIBUTES,
+};
 use proc_macro2::{Delimiter, Group, Ident, Literal, Span, TokenStream, TokenTree};
-use proc_macro_error::SpanRange;
+use proc_macro_error::{emit_error, SpanRange};
 use quote::quote;
 
 use crate::ast::*;
 
-pub fn generate(markups: Vec<Markup>, output_ident: TokenTree) -> TokenStream {
-    let mut build = Builder::new(output_ident.clone());
-    Generator::new(output_ident).markups(markups, &mut build);
+pub fn generate(
+    options: Options,
+    markups: Vec<Markup>,
+    output_ident: TokenTree,
+    flush_hook: Option<TokenStream>,
+    fallible: bool,
+) -> TokenStream {
+    let mut build = Builder::new(output_ident.clone(), &options, flush_hook.clone());
+    Generator::new(output_ident, options, flush_hook, fallible).markups(markups, &mut build);
     build.finish()
 }
 
+/// Generates the markup for a template with no splices, toggles or
+/// control structures, as a single string.
+///
+/// Emits an error if the template has any of these.
+pub fn generate_static(options: Options, markups: Vec<Markup>) -> String {
+    if let Some(span) = first_dynamic(&markups) {
+        emit_error!(
+            span,
+            "`html_static!` templates can't contain splices, toggles or control structures";
+            help = "use `html!` instead"
+        );
+        return String::new();
+    }
+    let output_ident = TokenTree::Ident(Ident::new("__maud_output", Span::mixed_site()));
+    let mut build = Builder::new(output_ident.clone(), &options, None);
+    Generator::new(output_ident, options, None, false).markups(markups, &mut build);
+    build.tail
+}
+
+/// Returns the span of the first part of the template that depends on
+/// runtime values.
+fn first_dynamic(markups: &[Markup]) -> Option<SpanRange> {
+    markups.iter().find_map(|markup| match markup {
+        Markup::ParseError { .. } | Markup::Literal { .. } | Markup::Symbol { .. } => None,
+        Markup::Block(block) => first_dynamic(&block.markups),
+        Markup::Element { attrs, body, .. } => attrs
+            .iter()
+            .find_map(|attr| match attr {
+                Attr::Class {
+                    toggler: Some(_), ..
+                }
+                | Attr::Attribute {
+                    attribute:
+                        Attribute {
+                            attr_type: AttrType::Empty { toggler: Some(_) },
+                            ..
+                        },
+                } => Some(attr.span()),
+                Attr::Class { name, .. } | Attr::Id { name, .. } => {
+                    first_dynamic(std::slice::from_ref(name))
+                }
+                Attr::Attribute {
+                    attribute:
+                        Attribute {
+                            attr_type: AttrType::Normal { value },
+                            ..
+                        },
+                } => first_dynamic(std::slice::from_ref(value)),
+                Attr::Attribute {
+                    attribute:
+                        Attribute {
+                            attr_type: AttrType::Optional { .. },
+                            ..
+                        },
+                }
+                | Attr::Spread { .. } => Some(attr.span()),
+                Attr::Attribute { .. } => None,
+            })
+            .or_else(|| match body {
+                ElementBody::Block { block } => first_dynamic(&block.markups),
+                ElementBody::Void { .. } => None,
+            }),
+        Markup::Splice { .. }
+        | Markup::DynamicElement { .. }
+        | Markup::Let { .. }
+        | Markup::Special { .. }
+        | Markup::Match { .. } => Some(markup.span()),
+    })
+}
+
 struct Generator {
     output_ident: TokenTree,
+    /// Where the markup being generated will end up.
+    context: Context,
+    options: Options,
+    /// Statements that send the markup so far once it is big enough, as
+    /// in `html_stream!`. These run after every splice, at the end of
+    /// every `@for` and `@while` iteration, and after long literal text.
+    flush_hook: Option<TokenStream>,
+    /// Whether splices can fail, as in `try_html!`.
+    fallible: bool,
+    /// How many elements deep the generated markup is, for `html_pretty!`.
+    depth: usize,
+    /// Whether we're inside an element whose whitespace matters, such
+    /// as `pre`.
+    preformatted: bool,
+    /// `Some` while generating a URL attribute value that might need
+    /// its scheme checked at the end, and whether it has a splice at
+    /// the start.
+    url_start_splice: Option<bool>,
 }
 
 impl Generator {
-    fn new(output_ident: TokenTree) -> Generator {
-        Generator { output_ident }
+    fn new(
+        output_ident: TokenTree,
+        options: Options,
+        flush_hook: Option<TokenStream>,
+        fallible: bool,
+    ) -> Generator {
+        Generator {
+            output_ident,
+            context: Context::Html,
+            options,
+            flush_hook,
+            fallible,
+            depth: 0,
+            preformatted: false,
+            url_start_splice: None,
+        }
     }
 
     fn builder(&self) -> Builder {
-        Builder::new(self.output_ident.clone())
+        Builder::new(
+            self.output_ident.clone(),
+            &self.options,
+            self.flush_hook.clone(),
+        )
     }
 
-    fn markups(&self, markups: Vec<Markup>, build: &mut Builder) {
+    fn markups(&mut self, markups: Vec<Markup>, build: &mut Builder) {
         for markup in markups {
             self.markup(markup, build);
         }
     }
 
-    fn markup(&self, markup: Markup, build: &mut Builder) {
+    fn markup(&mut self, markup: Markup, build: &mut Builder) {
         match markup {
             Markup::ParseError { .. } => {}
             Markup::Block(Block {
@@ -49,15 +164,44 @@ impl Generator {
                     self.markups(markups, build);
                 }
             }
-            Markup::Literal { content, .. } => build.push_escaped(&content),
+            Markup::Literal { content, span } => self.literal(content, span, build),
             Markup::Symbol { symbol } => self.name(symbol, build),
-            Markup::Splice { expr, .. } => build.push_tokens(self.splice(expr)),
+            Markup::Splice { expr, outer_span } => build.push_tokens(self.splice(expr, outer_span)),
             Markup::Element { name, attrs, body } => self.element(name, attrs, body, build),
+            Markup::DynamicElement {
+                name,
+                name_span,
+                attrs,
+                body,
+                ..
+            } => self.dynamic_element(name, name_span, attrs, body, build),
             Markup::Let { tokens, .. } => build.push_tokens(tokens),
             Markup::Special { segments } => {
+                let span = match (segments.first(), segments.last()) {
+                    (Some(first), Some(last)) => first.at_span.join_range(last.span()),
+                    _ => return,
+                };
+                let start = self.context;
+                let mut ends = Vec::new();
+                // A loop can run any number of times, and an `@if` without
+                // an `@else` can be skipped, so the context they start in is
+                // also one that they can end in
+                if segments[0].is_loop() || !segments[segments.len() - 1].is_else() {
+                    ends.push(start);
+                }
+                // A loop body is generated once, but it can run after
+                // earlier iterations have written the start of a URL
+                let body_start = if segments[0].is_loop() {
+                    start.past_url_start()
+                } else {
+                    start
+                };
                 for segment in segments {
+                    self.context = body_start;
                     build.push_tokens(self.special(segment));
+                    ends.push(self.context);
                 }
+                self.join_branches(start, ends, span);
             }
             Markup::Match {
                 head,
@@ -65,18 +209,27 @@ impl Generator {
                 arms_span,
                 ..
             } => {
-                build.push_tokens({
-                    let body = arms.into_iter().map(|arm| self.match_arm(arm)).collect();
-                    let mut body = TokenTree::Group(Group::new(Delimiter::Brace, body));
-                    body.set_span(arms_span.collapse());
-                    quote!(#head #body)
-                });
+                let start = self.context;
+                let mut ends = Vec::new();
+                let body = arms
+                    .into_iter()
+                    .map(|arm| {
+                        self.context = start;
+                        let arm = self.match_arm(arm);
+                        ends.push(self.context);
+                        arm
+                    })
+                    .collect();
+                let mut body = TokenTree::Group(Group::new(Delimiter::Brace, body));
+                body.set_span(arms_span.collapse());
+                build.push_tokens(quote!(#head #body));
+                self.join_branches(start, ends, arms_span);
             }
         }
     }
 
     fn block(
-        &self,
+        &mut self,
         Block {
             markups,
             outer_span,
@@ -89,45 +242,295 @@ impl Generator {
         TokenStream::from(block)
     }
 
-    fn splice(&self, expr: TokenStream) -> TokenStream {
+    fn literal(&mut self, content: String, span: SpanRange, build: &mut Builder) {
+        if let Some(element) = self.context.raw_text_element() {
+            // The contents of `script` and `style` elements are not
+            // unescaped by the browser, so they must be written as-is
+            let end_tag = format!("</{}", element);
+            if content.to_ascii_lowercase().contains(&end_tag) {
+                emit_error!(
+                    span,
+                    "literal contains `{}`, which would end the `{}` element early",
+                    end_tag,
+                    element,
+                );
+            }
+            build.push_str(&encode_raw_text(&content, element, self.options.charset));
+        } else {
+            build.push_escaped(&content);
+        }
+        self.context.advance(&content);
+    }
+
+    fn splice(&mut self, expr: TokenStream, outer_span: SpanRange) -> TokenStream {
         let output_ident = self.output_ident.clone();
-        quote!({
-            use maud::render::{RenderInternal, RenderWrapper};
-            RenderWrapper(&#expr).__maud_render_to(&mut #output_ident);
-        })
+        // Keep the parentheses, so that `(a + b)` borrows the sum
+        let mut expr = TokenTree::Group(Group::new(Delimiter::Parenthesis, expr));
+        expr.set_span(outer_span.collapse());
+        if let Some((message, help)) = self.context.splice_error() {
+            emit_error!(outer_span, message; help = help);
+        }
+        let context = self.context.splice_context(&self.options);
+        let mut flush = self.flush_hook.clone().unwrap_or_default();
+        if let (
+            Context::Url {
+                part: UrlPart::Start,
+            },
+            Some(url_start_splice),
+        ) = (self.context, &mut self.url_start_splice)
+        {
+            // Note where the splice ends, for `url_value`
+            *url_start_splice = true;
+            let splice_end_ident = Ident::new("__maud_url_splice_end", Span::mixed_site());
+            flush = quote!(#splice_end_ident = #output_ident.len(););
+        }
+        self.context.advance_splice();
+        if self.fallible {
+            quote!({
+                use maud::render::{
+                    RenderCollection, RenderInternal, RenderIterator, RenderJsLiteral,
+                    RenderPreEscaped, RenderWrapper, TryRenderInternal,
+                };
+                // `?` would leave the error type of Render values unknown
+                #[allow(clippy::question_mark)]
+                if let ::std::result::Result::Err(error) =
+                    RenderWrapper(&#expr).__maud_try_render_to(#context, &mut #output_ident)
+                {
+                    return ::std::result::Result::Err(error);
+                }
+                #flush
+            })
+        } else {
+            quote!({
+                use maud::render::{
+                    RenderCollection, RenderInternal, RenderIterator, RenderJsLiteral,
+                    RenderPreEscaped, RenderWrapper,
+                };
+                RenderWrapper(&#expr).__maud_render_to(#context, &mut #output_ident);
+                #flush
+            })
+        }
     }
 
-    fn element(&self, name: TokenStream, attrs: Vec<Attr>, body: ElementBody, build: &mut Builder) {
+    fn element(
+        &mut self,
+        name: TokenStream,
+        attrs: Vec<Attr>,
+        body: ElementBody,
+        build: &mut Builder,
+    ) {
+        let name_str = name_to_string(name.clone());
+        let pretty = self.options.pretty && !self.preformatted;
+        if pretty && is_block_element(&name_str) {
+            self.push_newline(build);
+        }
         build.push_str("<");
         self.name(name.clone(), build);
         self.attrs(attrs, build);
         build.push_str(">");
         if let ElementBody::Block { block } = body {
+            let outer_context = self.context;
+            let outer_preformatted = self.preformatted;
+            self.context = Context::for_element(&name_str);
+            self.preformatted |=
+                PREFORMATTED_ELEMENTS.contains(&name_str.to_ascii_lowercase().as_str());
+            let end_on_new_line = pretty && !self.preformatted && has_block_element(&block.markups);
+            self.depth += 1;
             self.markups(block.markups, build);
+            self.depth -= 1;
+            if let Some(unclosed) = self.context.unclosed() {
+                emit_error!(
+                    span_tokens(name.clone()),
+                    "this `{}` element ends inside {}", name_str, unclosed;
+                    help = "close it before the end of the element"
+                );
+            }
+            self.context = outer_context;
+            self.preformatted = outer_preformatted;
+            if end_on_new_line {
+                self.push_newline(build);
+            }
             build.push_str("</");
             self.name(name, build);
             build.push_str(">");
         }
     }
 
+    fn dynamic_element(
+        &mut self,
+        name: TokenStream,
+        name_span: SpanRange,
+        attrs: Vec<Attr>,
+        body: ElementBody,
+        build: &mut Builder,
+    ) {
+        let output_ident = self.output_ident.clone();
+        let name_ident = Ident::new("__maud_element_name", Span::mixed_site());
+        let mut name = TokenTree::Group(Group::new(Delimiter::Parenthesis, name));
+        name.set_span(name_span.collapse());
+        let push_name = quote!(#output_ident.push_str(#name_ident););
+        // The name isn't known until runtime, so `html_pretty!` treats
+        // it as a block element, but doesn't indent its contents
+        let pretty = self.options.pretty && !self.preformatted;
+        if pretty {
+            self.push_newline(build);
+        }
+        let mut element = self.builder();
+        element.push_str("<");
+        element.push_tokens(push_name.clone());
+        self.attrs(attrs, &mut element);
+        element.push_str(">");
+        let has_body = matches!(&body, ElementBody::Block { block } if !block.markups.is_empty());
+        if let ElementBody::Block { block } = body {
+            let outer_context = self.context;
+            let outer_preformatted = self.preformatted;
+            self.context = Context::Html;
+            // The element might be a `pre` or `textarea`, so leave its
+            // contents as they are
+            self.preformatted = true;
+            self.markups(block.markups, &mut element);
+            self.context = outer_context;
+            self.preformatted = outer_preformatted;
+        }
+        let end_tag = {
+            let mut build = self.builder();
+            build.push_str("</");
+            build.push_tokens(push_name);
+            build.push_str(">");
+            build.finish()
+        };
+        element.push_tokens(quote!(if !maud::render::is_void_element(#name_ident) { #end_tag }));
+        let element = element.finish();
+        build.push_tokens(quote!({
+            let #name_ident = &#name;
+            let #name_ident: &str = maud::render::element_name(#name_ident, #has_body);
+            #element
+        }));
+    }
+
+    /// Starts a new line, indented to the current depth.
+    ///
+    /// This also comes before the first element, so `html_pretty!`
+    /// removes a leading newline from the result.
+    fn push_newline(&self, build: &mut Builder) {
+        build.push_str("\n");
+        build.push_str(&"  ".repeat(self.depth));
+    }
+
     fn name(&self, name: TokenStream, build: &mut Builder) {
-        build.push_escaped(&name_to_string(name));
+        // Names are checked when parsing, so they don't need escaping
+        build.push_str(&name_to_string(name));
     }
 
-    fn attrs(&self, attrs: Vec<Attr>, build: &mut Builder) {
-        for Attribute { name, attr_type } in desugar_attrs(attrs) {
+    fn attrs(&mut self, attrs: Vec<Attr>, build: &mut Builder) {
+        let (attributes, spreads) = desugar_attrs(attrs);
+        // Spread attributes can't override the ones written out here.
+        // Toggled and optional attributes only count if they are
+        // written, which is tracked at runtime.
+        let written_ident = Ident::new("__maud_written", Span::mixed_site());
+        let mut known_names = Vec::new();
+        let mut conditional = 0;
+        if !spreads.is_empty() {
+            let conditional = attributes
+                .iter()
+                .filter(|attribute| is_conditional(&attribute.attr_type))
+                .count();
+            if conditional > 0 {
+                build.push_tokens(quote!(let mut #written_ident = [false; #conditional];));
+            }
+        }
+        for Attribute { name, attr_type } in attributes {
+            // Marks a conditional attribute as written
+            let mut mark_written = TokenStream::new();
+            if !spreads.is_empty() {
+                let lowercase = name_to_string(name.clone()).to_ascii_lowercase();
+                if is_conditional(&attr_type) {
+                    let index = Literal::usize_unsuffixed(conditional);
+                    conditional += 1;
+                    mark_written = quote!(#written_ident[#index] = true;);
+                    // No valid attribute name is empty, so this matches
+                    // nothing if the attribute wasn't written
+                    known_names.push(quote!(if #written_ident[#index] { #lowercase } else { "" }));
+                } else {
+                    known_names.push(quote!(#lowercase));
+                }
+            }
             match attr_type {
                 AttrType::Normal { value } => {
                     build.push_str(" ");
-                    self.name(name, build);
+                    self.name(name.clone(), build);
                     build.push_str("=\"");
-                    self.markup(value, build);
+                    let outer_context = self.context;
+                    self.context = Context::for_attribute(&name_to_string(name.clone()));
+                    if let Context::Url { .. } = self.context {
+                        self.url_value(value, build);
+                    } else {
+                        self.markup(value, build);
+                    }
+                    if let Some(unclosed) = self.context.unclosed() {
+                        emit_error!(
+                            span_tokens(name),
+                            "this attribute value ends inside {}", unclosed;
+                            help = "close it before the end of the value"
+                        );
+                    }
+                    self.context = outer_context;
                     build.push_str("\"");
                 }
+                AttrType::Optional { toggler } => {
+                    let value_ident = Ident::new("__maud_value", Span::mixed_site());
+                    let value_span = toggler.cond_span;
+                    let head = desugar_optional(toggler, &value_ident);
+                    build.push_tokens({
+                        let mut build = self.builder();
+                        build.push_str(" ");
+                        self.name(name.clone(), &mut build);
+                        build.push_str("=\"");
+                        let outer_context = self.context;
+                        self.context = Context::for_attribute(&name_to_string(name));
+                        build.push_tokens(self.splice(quote!(#value_ident), value_span));
+                        self.context = outer_context;
+                        build.push_str("\"");
+                        let body = build.finish();
+                        quote!(#head { #body #mark_written })
+                    })
+                }
                 AttrType::Empty { toggler: None } => {
                     build.push_str(" ");
                     self.name(name, build);
                 }
+                AttrType::Merged { values } => {
+                    let output_ident = self.output_ident.clone();
+                    let merged_ident = Ident::new("__maud_merged", Span::mixed_site());
+                    let name = name_to_string(name);
+                    // Write each value to a separate buffer, since the
+                    // attribute is left out if they are all empty
+                    let outer_output_ident = std::mem::replace(
+                        &mut self.output_ident,
+                        TokenTree::Group(Group::new(
+                            Delimiter::Parenthesis,
+                            quote!(*#merged_ident.buffer()),
+                        )),
+                    );
+                    let outer_context = self.context;
+                    let mut values_build = self.builder();
+                    for value in values {
+                        self.context = Context::for_attribute(&name);
+                        values_build.push_tokens(quote!(#merged_ident.start_value();));
+                        self.markup(value, &mut values_build);
+                        values_build.push_tokens(quote!(#merged_ident.end_value();));
+                    }
+                    self.context = outer_context;
+                    self.output_ident = outer_output_ident;
+                    let values = values_build.finish();
+                    build.push_tokens(quote!({
+                        let mut #merged_ident = maud::render::MergedAttr::default();
+                        #values
+                        if #merged_ident.finish(#name, &mut #output_ident) {
+                            #mark_written
+                        }
+                    }));
+                }
                 AttrType::Empty {
                     toggler: Some(toggler),
                 } => {
@@ -137,32 +540,648 @@ impl Generator {
                         build.push_str(" ");
                         self.name(name, &mut build);
                         let body = build.finish();
-                        quote!(#head { #body })
+                        quote!(#head { #body #mark_written })
                     })
                 }
             }
         }
+        for spread in spreads {
+            build.push_tokens(self.spread(spread, &known_names));
+        }
     }
 
-    fn special(&self, Special { head, body, .. }: Special) -> TokenStream {
-        let body = self.block(body);
-        quote!(#head #body)
+    /// Generates the value of a URL attribute.
+    ///
+    /// A splice at the start only checks its own scheme, so if the
+    /// value starts with one, the whole value is checked again at the
+    /// end. Nothing is flushed in the meantime, so that the check can
+    /// still replace the value.
+    fn url_value(&mut self, value: Markup, build: &mut Builder) {
+        if first_dynamic(std::slice::from_ref(&value)).is_none() {
+            self.markup(value, build);
+            return;
+        }
+        let context = self.context.splice_context(&self.options);
+        let outer_flush_hook = self.flush_hook.take();
+        self.url_start_splice = Some(false);
+        let mut value_build = self.builder();
+        self.markup(value, &mut value_build);
+        let value = value_build.finish();
+        if self.url_start_splice == Some(true) {
+            let output_ident = self.output_ident.clone();
+            let start_ident = Ident::new("__maud_url_start", Span::mixed_site());
+            let splice_end_ident = Ident::new("__maud_url_splice_end", Span::mixed_site());
+            build.push_tokens(quote!({
+                let #start_ident = #output_ident.len();
+                #[allow(unused_assignments)]
+                let mut #splice_end_ident = #start_ident;
+                #value
+                maud::render::check_url(
+                    #context,
+                    #start_ident..#splice_end_ident,
+                    &mut #output_ident,
+                );
+            }));
+        } else {
+            build.push_tokens(value);
+        }
+        self.flush_hook = outer_flush_hook;
+        self.url_start_splice = None;
     }
 
-    fn match_arm(&self, MatchArm { head, body }: MatchArm) -> TokenStream {
+    fn spread(
+        &self,
+        Spread {
+            expr, outer_span, ..
+        }: Spread,
+        known_names: &[TokenStream],
+    ) -> TokenStream {
+        let output_ident = self.output_ident.clone();
+        let mut expr = TokenTree::Group(Group::new(Delimiter::Parenthesis, expr));
+        expr.set_span(outer_span.collapse());
+        // The runtime picks the position from each name, but takes the
+        // profile, charset and URL schemes from here
+        let context = Context::Url {
+            part: UrlPart::Start,
+        }
+        .splice_context(&self.options);
+        quote!({
+            // The parentheses keep `..(a, b)` from becoming two arguments
+            #[allow(unused_parens)]
+            maud::render::spread_attrs(#expr, #context, &[#(#known_names),*], &mut #output_ident);
+        })
+    }
+
+    fn special(&mut self, special: Special) -> TokenStream {
+        let is_loop = special.is_loop();
+        let Special { head, body, .. } = special;
+        match &self.flush_hook {
+            Some(flush_hook) if is_loop => {
+                let flush_hook = flush_hook.clone();
+                let Block {
+                    markups,
+                    outer_span,
+                } = body;
+                let mut build = self.builder();
+                self.markups(markups, &mut build);
+                build.push_tokens(flush_hook);
+                let mut body = TokenTree::Group(Group::new(Delimiter::Brace, build.finish()));
+                body.set_span(outer_span.collapse());
+                quote!(#head #body)
+            }
+            _ => {
+                let body = self.block(body);
+                quote!(#head #body)
+            }
+        }
+    }
+
+    fn match_arm(&mut self, MatchArm { head, body }: MatchArm) -> TokenStream {
         let body = self.block(body);
         quote!(#head #body)
     }
+
+    /// Sets the context after a control structure, given the contexts
+    /// that its branches can end in.
+    fn join_branches(&mut self, start: Context, ends: Vec<Context>, span: SpanRange) {
+        let mut joined: Option<Context> = None;
+        for end in ends {
+            joined = match joined {
+                None => Some(end),
+                Some(context) => match context.join(end) {
+                    Some(context) => Some(context),
+                    None => {
+                        emit_error!(
+                            span,
+                            "this control structure can end inside or outside of a JavaScript string, depending on which branch runs";
+                            help = "close each string in the same branch or loop body that opens it"
+                        );
+                        self.context = start;
+                        return;
+                    }
+                },
+            };
+        }
+        // The branches may or may not have written the start of a URL,
+        // so a splice after them can't be trusted to set the scheme
+        self.context = joined.unwrap_or(start).past_url_start();
+    }
+}
+
+////////////////////////////////////////////////////////
+
+/// The kind of position that markup is generated for.
+///
+/// This decides how splices are escaped at runtime.
+#[derive(Clone, Copy)]
+enum Context {
+    /// Element content.
+    Html,
+    /// The contents of a `script` element.
+    Script { js: Js },
+    /// The contents of a `style` element.
+    Style,
+    /// A plain attribute value.
+    Attribute,
+    /// The value of an attribute that holds a space-separated list of
+    /// tokens, such as `class`.
+    TokenList,
+    /// The value of an attribute that holds a URL, such as `href`.
+    Url { part: UrlPart },
+    /// The value of an event handler attribute, such as `onclick`.
+    EventHandler { js: Js },
+    /// The value of a `style` attribute.
+    StyleAttribute,
+}
+
+/// How far into a URL-valued attribute the generated markup is.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum UrlPart {
+    /// Nothing has been written yet, so a splice decides the scheme.
+    Start,
+    /// Before the query string or fragment.
+    Path,
+    /// In the query string or fragment.
+    Query,
+}
+
+/// How far into JavaScript code the generated markup is, in a `script`
+/// element or an event handler attribute.
+///
+/// This follows just enough of the syntax to tell whether a splice is
+/// inside a string literal: strings, template literals, comments and
+/// regular expressions.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Js {
+    mode: JsMode,
+    /// The number of unclosed braces in each `${...}` of a template
+    /// literal that the code is nested in, innermost last.
+    braces: [u8; MAX_TEMPLATE_DEPTH],
+    depth: usize,
+}
+
+/// How deeply template literals can be nested before the code is no
+/// longer followed.
+const MAX_TEMPLATE_DEPTH: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum JsMode {
+    /// Outside of any literal or comment. `regex` is whether a `/` here
+    /// would start a regular expression rather than be a division, or
+    /// `None` if that can't be told.
+    Code { regex: Option<bool> },
+    /// Inside a string literal with the given quote, or the text of a
+    /// template literal if the quote is `` ` ``.
+    String { quote: char },
+    /// Inside a `//` comment, in code where a `/` would or wouldn't
+    /// start a regular expression.
+    LineComment { regex: Option<bool> },
+    /// Inside a `/* */` comment.
+    BlockComment { regex: Option<bool> },
+    /// Inside a regular expression literal, and maybe inside a `[...]`
+    /// character class.
+    Regex { class: bool },
+    /// The code can't be followed any further, such as after a `/` that
+    /// might start either a regular expression or a division.
+    Unknown,
+}
+
+/// Keywords that can be followed by an expression, and so by a regular
+/// expression literal.
+const JS_KEYWORDS: &[&str] = &[
+    "await",
+    "case",
+    "delete",
+    "do",
+    "else",
+    "in",
+    "instanceof",
+    "new",
+    "of",
+    "return",
+    "throw",
+    "typeof",
+    "void",
+    "yield",
+];
+
+impl Js {
+    fn new() -> Js {
+        Js {
+            mode: JsMode::Code { regex: Some(true) },
+            braces: [0; MAX_TEMPLATE_DEPTH],
+            depth: 0,
+        }
+    }
+
+    /// Merges the states that two branches end in, or returns `None` if
+    /// they are inside different literals.
+    fn join(self, other: Js) -> Option<Js> {
+        match (self.mode, other.mode) {
+            _ if self == other => Some(self),
+            (JsMode::Code { .. }, JsMode::Code { .. })
+                if (self.braces, self.depth) == (other.braces, other.depth) =>
+            {
+                Some(Js {
+                    mode: JsMode::Code { regex: None },
+                    ..self
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Updates the state after the given code.
+    fn advance(&mut self, text: &str) {
+        let mut chars = text.chars().peekable();
+        // The identifier, keyword or number being read
+        let mut word = String::new();
+        while let Some(c) = chars.next() {
+            if let JsMode::Code { .. } = self.mode {
+                if !is_js_word_char(c) && !word.is_empty() {
+                    self.mode = JsMode::Code {
+                        regex: Some(JS_KEYWORDS.contains(&word.as_str())),
+                    };
+                    word.clear();
+                }
+            }
+            let next = chars.peek().copied();
+            self.mode = match self.mode {
+                JsMode::Unknown => return,
+                JsMode::Code { regex } => match c {
+                    c if c.is_whitespace() => continue,
+                    c if is_js_word_char(c) => {
+                        word.push(c);
+                        JsMode::Code { regex: Some(false) }
+                    }
+                    '"' | '\'' | '`' => JsMode::String { quote: c },
+                    '/' => match next {
+                        Some('/') => {
+                            chars.next();
+                            JsMode::LineComment { regex }
+                        }
+                        Some('*') => {
+                            chars.next();
+                            JsMode::BlockComment { regex }
+                        }
+                        // The rest of the token might come after a splice
+                        None => JsMode::Unknown,
+                        Some(_) => match regex {
+                            Some(true) => JsMode::Regex { class: false },
+                            Some(false) => JsMode::Code { regex: Some(true) },
+                            None => JsMode::Unknown,
+                        },
+                    },
+                    ')' | ']' => JsMode::Code { regex: Some(false) },
+                    '{' => {
+                        if self.depth > 0 {
+                            self.braces[self.depth - 1] += 1;
+                        }
+                        JsMode::Code { regex: Some(true) }
+                    }
+                    '}' if self.depth > 0 && self.braces[self.depth - 1] == 0 => {
+                        // The end of a `${...}` in a template literal
+                        self.depth -= 1;
+                        JsMode::String { quote: '`' }
+                    }
+                    '}' => {
+                        if self.depth > 0 {
+                            self.braces[self.depth - 1] -= 1;
+                        }
+                        // This could end either a block or an object
+                        JsMode::Code { regex: None }
+                    }
+                    '+' | '-' if next == Some(c) => {
+                        chars.next();
+                        JsMode::Code { regex: None }
+                    }
+                    _ => JsMode::Code { regex: Some(true) },
+                },
+                JsMode::String { quote } => match c {
+                    '\\' => match chars.next() {
+                        Some(_) => continue,
+                        None => JsMode::Unknown,
+                    },
+                    c if c == quote => JsMode::Code { regex: Some(false) },
+                    '$' if quote == '`' => match next {
+                        Some('{') if self.depth < MAX_TEMPLATE_DEPTH => {
+                            chars.next();
+                            self.braces[self.depth] = 0;
+                            self.depth += 1;
+                            JsMode::Code { regex: Some(true) }
+                        }
+                        Some('{') | None => JsMode::Unknown,
+                        Some(_) => continue,
+                    },
+                    _ => continue,
+                },
+                JsMode::LineComment { regex } => match c {
+                    '\n' | '\r' | '\u{2028}' | '\u{2029}' => JsMode::Code { regex },
+                    _ => continue,
+                },
+                JsMode::BlockComment { regex } => match (c, next) {
+                    ('*', Some('/')) => {
+                        chars.next();
+                        JsMode::Code { regex }
+                    }
+                    ('*', None) => JsMode::Unknown,
+                    _ => continue,
+                },
+                JsMode::Regex { class } => match c {
+                    '\\' => match chars.next() {
+                        Some(_) => continue,
+                        None => JsMode::Unknown,
+                    },
+                    '[' => JsMode::Regex { class: true },
+                    ']' => JsMode::Regex { class: false },
+                    '/' if !class => JsMode::Code { regex: Some(false) },
+                    '\n' | '\r' => JsMode::Unknown,
+                    _ => continue,
+                },
+            };
+        }
+        if !word.is_empty() {
+            self.mode = JsMode::Code {
+                regex: Some(JS_KEYWORDS.contains(&word.as_str())),
+            };
+        }
+    }
+
+    /// Updates the state after a splice.
+    fn advance_splice(&mut self) {
+        if let JsMode::Code { .. } = self.mode {
+            // The splice is a value, so a `/` after it is a division
+            self.mode = JsMode::Code { regex: Some(false) };
+        }
+    }
+
+    /// Returns what the code is still inside of, if it isn't closed.
+    fn unclosed(self) -> Option<&'static str> {
+        match self.mode {
+            JsMode::String { quote: '`' } => Some("a template literal"),
+            JsMode::String { .. } => Some("a string"),
+            JsMode::BlockComment { .. } => Some("a comment"),
+            JsMode::Regex { .. } => Some("a regular expression"),
+            JsMode::Code { .. } if self.depth > 0 => Some("a template literal"),
+            JsMode::Code { .. } | JsMode::LineComment { .. } | JsMode::Unknown => None,
+        }
+    }
+}
+
+fn is_js_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Elements that are laid out inline, and so aren't put on a new line
+/// by `html_pretty!`.
+const INLINE_ELEMENTS: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "br", "button", "cite", "code", "data", "dfn", "em", "i",
+    "img", "input", "kbd", "label", "mark", "q", "s", "samp", "select", "small", "span", "strong",
+    "sub", "sup", "textarea", "time", "u", "var", "wbr",
+];
+
+/// Elements whose contents `html_pretty!` leaves alone, since adding
+/// whitespace would change them.
+const PREFORMATTED_ELEMENTS: &[&str] = &["pre", "script", "style", "textarea"];
+
+fn is_block_element(name: &str) -> bool {
+    !INLINE_ELEMENTS.contains(&name.to_ascii_lowercase().as_str())
+}
+
+/// Returns whether any of the markups, including those in control
+/// structures, is a block element.
+fn has_block_element(markups: &[Markup]) -> bool {
+    markups.iter().any(|markup| match markup {
+        Markup::Element { name, .. } => is_block_element(&name_to_string(name.clone())),
+        Markup::DynamicElement { .. } => true,
+        Markup::Block(block) => has_block_element(&block.markups),
+        Markup::Special { segments } => segments
+            .iter()
+            .any(|segment| has_block_element(&segment.body.markups)),
+        Markup::Match { arms, .. } => arms.iter().any(|arm| has_block_element(&arm.body.markups)),
+        _ => false,
+    })
+}
+
+impl Context {
+    fn for_element(name: &str) -> Context {
+        match name.to_ascii_lowercase().as_str() {
+            "script" => Context::Script { js: Js::new() },
+            "style" => Context::Style,
+            _ => Context::Html,
+        }
+    }
+
+    fn for_attribute(name: &str) -> Context {
+        let name = name.to_ascii_lowercase();
+        if URL_ATTRIBUTES.contains(&name.as_str()) {
+            Context::Url {
+                part: UrlPart::Start,
+            }
+        } else if is_script_attribute(&name) {
+            Context::EventHandler { js: Js::new() }
+        } else if name == "style" {
+            Context::StyleAttribute
+        } else if TOKEN_LIST_ATTRIBUTES.contains(&name.as_str()) {
+            Context::TokenList
+        } else {
+            Context::Attribute
+        }
+    }
+
+    /// Returns the name of the element if literals should be written
+    /// without escaping.
+    fn raw_text_element(self) -> Option<&'static str> {
+        match self {
+            Context::Script { .. } => Some("script"),
+            Context::Style => Some("style"),
+            _ => None,
+        }
+    }
+
+    /// Merges the contexts that two branches of a control structure end
+    /// in, or returns `None` if they are in different quote states.
+    fn join(self, other: Context) -> Option<Context> {
+        match (self, other) {
+            (Context::Script { js: a }, Context::Script { js: b }) => {
+                a.join(b).map(|js| Context::Script { js })
+            }
+            (Context::EventHandler { js: a }, Context::EventHandler { js: b }) => {
+                a.join(b).map(|js| Context::EventHandler { js })
+            }
+            (Context::Url { part: a }, Context::Url { part: b }) => {
+                Some(Context::Url { part: a.max(b) })
+            }
+            _ => Some(self),
+        }
+    }
+
+    /// Returns this context, moved past the start of a URL if it is at
+    /// the start of one.
+    fn past_url_start(self) -> Context {
+        match self {
+            Context::Url {
+                part: UrlPart::Start,
+            } => Context::Url {
+                part: UrlPart::Path,
+            },
+            _ => self,
+        }
+    }
+
+    /// Updates the context after the given literal text.
+    fn advance(&mut self, text: &str) {
+        match self {
+            Context::Script { js } | Context::EventHandler { js } => js.advance(text),
+            Context::Url { part } if text.contains(['?', '#']) => *part = UrlPart::Query,
+            Context::Url {
+                part: part @ UrlPart::Start,
+            } if !text.is_empty() => *part = UrlPart::Path,
+            _ => {}
+        }
+    }
+
+    /// Updates the context after a splice.
+    fn advance_splice(&mut self) {
+        match self {
+            Context::Url {
+                part: part @ UrlPart::Start,
+            } => *part = UrlPart::Path,
+            Context::Script { js } | Context::EventHandler { js } => js.advance_splice(),
+            _ => {}
+        }
+    }
+
+    /// Returns why a splice can't be escaped here, along with a hint to
+    /// fix it, if it can't.
+    fn splice_error(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Context::Script { js } | Context::EventHandler { js } => match js.mode {
+                JsMode::Regex { .. } => Some((
+                    "can't splice into a JavaScript regular expression",
+                    "build the pattern with `new RegExp(...)` from a spliced string instead",
+                )),
+                JsMode::Unknown => Some((
+                    "can't tell whether this splice is inside a JavaScript string",
+                    "a `/` after `}`, `++` or `--` could start either a regular expression or a division; \
+                     rewrite that code, or move the splice before it",
+                )),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns what the JavaScript code is still inside of, if it was
+    /// left unclosed at the end of a `script` element or an event
+    /// handler.
+    fn unclosed(self) -> Option<&'static str> {
+        match self {
+            Context::Script { js } | Context::EventHandler { js } => js.unclosed(),
+            _ => None,
+        }
+    }
+
+    /// Returns the runtime context to escape a splice with.
+    fn splice_context(self, options: &Options) -> TokenStream {
+        let position = match self {
+            Context::Html => "Html",
+            Context::Attribute | Context::TokenList => "Attribute",
+            Context::Url {
+                part: UrlPart::Start,
+            } => "Url",
+            Context::Url {
+                part: UrlPart::Path,
+            } => "UrlPath",
+            Context::Url {
+                part: UrlPart::Query,
+            } => "UrlComponent",
+            Context::Script { js } | Context::EventHandler { js } => {
+                if let JsMode::Code { .. } = js.mode {
+                    "JsValue"
+                } else {
+                    "JsString"
+                }
+            }
+            Context::Style | Context::StyleAttribute => "Css",
+        };
+        let position = Ident::new(position, Span::call_site());
+        let mut context = quote!(maud::Context::new(maud::Position::#position));
+        let profile = match options.profile {
+            Profile::Minimal => None,
+            Profile::Xml => Some("Xml"),
+            Profile::Strict => Some("Strict"),
+        };
+        if let Some(profile) = profile {
+            let profile = Ident::new(profile, Span::call_site());
+            context.extend(quote!(.with_profile(maud::Profile::#profile)));
+        }
+        if options.charset != Charset::UTF8 {
+            let ascii = if options.charset.is_ascii_only() {
+                "ASCII"
+            } else {
+                "UTF8"
+            };
+            let ascii = Ident::new(ascii, Span::call_site());
+            let invalid_chars = match options.charset.invalid_chars() {
+                InvalidChars::Keep => "Keep",
+                InvalidChars::Strip => "Strip",
+                InvalidChars::Replace => "Replace",
+            };
+            let invalid_chars = Ident::new(invalid_chars, Span::call_site());
+            context.extend(quote!(.with_charset(
+                maud::Charset::#ascii.with_invalid_chars(maud::InvalidChars::#invalid_chars)
+            )));
+        }
+        if let Context::EventHandler { .. } | Context::StyleAttribute = self {
+            context.extend(quote!(.in_attribute()));
+        }
+        if let Context::TokenList = self {
+            context.extend(quote!(.in_token_list()));
+        }
+        if let (Context::Url { .. }, Some(schemes)) = (self, &options.url_schemes) {
+            context.extend(quote!(.with_url_schemes(&[#(#schemes),*])));
+        }
+        context
+    }
+}
+
+/// Applies the charset to a literal in a `script` or `style` element.
+///
+/// HTML references aren't decoded in these elements, so non-ASCII
+/// characters are written as JavaScript or CSS escapes instead.
+fn encode_raw_text(content: &str, element: &str, charset: Charset) -> String {
+    use std::fmt::Write;
+    let mut result = String::with_capacity(content.len());
+    for c in content.chars().filter_map(|c| charset.filter(c)) {
+        if c.is_ascii() || !charset.is_ascii_only() {
+            result.push(c);
+        } else if element == "script" {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                write!(result, "\\u{:04X}", unit).unwrap();
+            }
+        } else {
+            write!(result, "\\{:x} ", c as u32).unwrap();
+        }
+    }
+    result
 }
 
 ////////////////////////////////////////////////////////
 
-fn desugar_attrs(attrs: Vec<Attr>) -> Vec<Attribute> {
+/// Splits the attributes into those that are known at compile time,
+/// with the class and ID shorthands merged, and spreads.
+fn desugar_attrs(attrs: Vec<Attr>) -> (Vec<Attribute>, Vec<Spread>) {
     let mut classes_static = vec![];
     let mut classes_toggled = vec![];
+    let mut class_attrs = vec![];
     let mut ids = vec![];
     let mut attributes = vec![];
+    let mut spreads = vec![];
+    // A lone `class=` is written where it is, like any other attribute
+    let merge_classes = attrs.iter().filter(|attr| attr.is_class()).count() > 1;
     for attr in attrs {
+        let is_class = attr.is_class() && merge_classes;
         match attr {
             Attr::Class { name, toggler, .. } => {
                 if let Some(toggler) = toggler {
@@ -172,12 +1191,19 @@ fn desugar_attrs(attrs: Vec<Attr>) -> Vec<Attribute> {
                 }
             }
             Attr::Id { name, .. } => ids.push(name),
+            Attr::Attribute { attribute } if is_class => class_attrs.push(attribute),
             Attr::Attribute { attribute } => attributes.push(attribute),
+            Attr::Spread { spread } => spreads.push(spread),
         }
     }
-    let classes = desugar_classes_or_ids("class", classes_static, classes_toggled);
+    let classes = if class_attrs.is_empty() {
+        desugar_classes_or_ids("class", classes_static, classes_toggled)
+    } else {
+        desugar_merged_classes(classes_static, classes_toggled, class_attrs)
+    };
     let ids = desugar_classes_or_ids("id", ids, vec![]);
-    classes.into_iter().chain(ids).chain(attributes).collect()
+    let attributes = classes.into_iter().chain(ids).chain(attributes).collect();
+    (attributes, spreads)
 }
 
 fn desugar_classes_or_ids(
@@ -207,7 +1233,85 @@ fn desugar_classes_or_ids(
             }],
         });
     }
+    Some(class_or_id_attribute(attr_name, markups))
+}
+
+/// Merges class shorthands with `class=` values.
+///
+/// Empty values are skipped, and the attribute is left out if every
+/// value is empty. This is decided at compile time if all the values
+/// are known, and at runtime otherwise.
+fn desugar_merged_classes(
+    classes_static: Vec<Markup>,
+    classes_toggled: Vec<(Markup, Toggler)>,
+    class_attrs: Vec<Attribute>,
+) -> Option<Attribute> {
+    let mut values = Vec::new();
+    let mut values_optional = Vec::new();
+    for Attribute { attr_type, .. } in class_attrs {
+        match attr_type {
+            AttrType::Normal { value } => values.push(value),
+            AttrType::Optional { toggler } => values_optional.push(toggler),
+            _ => unreachable!("`class` should have a value"),
+        }
+    }
+    let mut values: Vec<Markup> = classes_static.into_iter().chain(values).collect();
+    if classes_toggled.is_empty() && values_optional.is_empty() && first_dynamic(&values).is_none()
+    {
+        let mut markups = Vec::new();
+        let mut leading_space = false;
+        for value in values {
+            if !is_empty_literal(&value) {
+                markups.extend(prepend_leading_space(value, &mut leading_space));
+            }
+        }
+        return if markups.is_empty() {
+            None
+        } else {
+            Some(class_or_id_attribute("class", markups))
+        };
+    }
+    for (name, toggler) in classes_toggled {
+        let outer_span = toggler.cond_span;
+        let head = desugar_toggler(toggler);
+        values.push(Markup::Special {
+            segments: vec![Special {
+                at_span: SpanRange::call_site(),
+                head,
+                body: Block {
+                    markups: vec![name],
+                    outer_span,
+                },
+            }],
+        });
+    }
+    for toggler in values_optional {
+        let value_ident = Ident::new("__maud_value", Span::mixed_site());
+        let value = Markup::Splice {
+            expr: quote!(#value_ident),
+            outer_span: toggler.cond_span,
+        };
+        let outer_span = toggler.cond_span;
+        let head = desugar_optional(toggler, &value_ident);
+        values.push(Markup::Special {
+            segments: vec![Special {
+                at_span: SpanRange::call_site(),
+                head,
+                body: Block {
+                    markups: vec![value],
+                    outer_span,
+                },
+            }],
+        });
+    }
     Some(Attribute {
+        name: TokenStream::from(TokenTree::Ident(Ident::new("class", Span::call_site()))),
+        attr_type: AttrType::Merged { values },
+    })
+}
+
+fn class_or_id_attribute(attr_name: &'static str, markups: Vec<Markup>) -> Attribute {
+    Attribute {
         name: TokenStream::from(TokenTree::Ident(Ident::new(attr_name, Span::call_site()))),
         attr_type: AttrType::Normal {
             value: Markup::Block(Block {
@@ -215,7 +1319,16 @@ fn desugar_classes_or_ids(
                 outer_span: SpanRange::call_site(),
             }),
         },
-    })
+    }
+}
+
+/// Returns whether the markup is known to render nothing.
+fn is_empty_literal(markup: &Markup) -> bool {
+    match markup {
+        Markup::Literal { content, .. } => content.is_empty(),
+        Markup::Block(block) => block.markups.iter().all(is_empty_literal),
+        _ => false,
+    }
 }
 
 fn prepend_leading_space(name: Markup, leading_space: &mut bool) -> Vec<Markup> {
@@ -231,7 +1344,28 @@ fn prepend_leading_space(name: Markup, leading_space: &mut bool) -> Vec<Markup>
     markups
 }
 
-fn desugar_toggler(
+fn desugar_toggler(toggler: Toggler) -> TokenStream {
+    let cond = toggler_cond(toggler);
+    quote!(if #cond)
+}
+
+/// Returns whether an attribute is only written when some condition
+/// holds at runtime.
+fn is_conditional(attr_type: &AttrType) -> bool {
+    matches!(
+        attr_type,
+        AttrType::Optional { .. } | AttrType::Empty { toggler: Some(_) } | AttrType::Merged { .. }
+    )
+}
+
+/// Returns the head of an `if let` that binds the value of an optional
+/// attribute, as in `title=[value]`, to `value_ident`.
+fn desugar_optional(toggler: Toggler, value_ident: &Ident) -> TokenStream {
+    let cond = toggler_cond(toggler);
+    quote!(if let ::std::option::Option::Some(ref #value_ident) = #cond)
+}
+
+fn toggler_cond(
     Toggler {
         mut cond,
         cond_span,
@@ -244,7 +1378,7 @@ fn desugar_toggler(
         wrapped_cond.set_span(cond_span.collapse());
         cond = TokenStream::from(wrapped_cond);
     }
-    quote!(if #cond)
+    cond
 }
 
 fn is_braced_block(token: TokenTree) -> bool {
@@ -255,14 +1389,24 @@ fn is_braced_block(token: TokenTree) -> bool {
 
 struct Builder {
     output_ident: TokenTree,
+    profile: Profile,
+    charset: Charset,
+    flush_hook: Option<TokenStream>,
     tokens: Vec<TokenTree>,
     tail: String,
 }
 
+/// In `html_stream!`, literal text at least this long is followed by a
+/// check for a full chunk.
+const LONG_TEXT_LEN: usize = 1024;
+
 impl Builder {
-    fn new(output_ident: TokenTree) -> Builder {
+    fn new(output_ident: TokenTree, options: &Options, flush_hook: Option<TokenStream>) -> Builder {
         Builder {
             output_ident,
+            profile: options.profile,
+            charset: options.charset,
+            flush_hook,
             tokens: Vec::new(),
             tail: String::new(),
         }
@@ -274,7 +1418,10 @@ impl Builder {
 
     fn push_escaped(&mut self, string: &str) {
         use std::fmt::Write;
-        Escaper::new(&mut self.tail).write_str(string).unwrap();
+        Escaper::with_profile(&mut self.tail, self.profile)
+            .with_charset(self.charset)
+            .write_str(string)
+            .unwrap();
     }
 
     fn push_tokens(&mut self, tokens: TokenStream) {
@@ -291,8 +1438,12 @@ impl Builder {
             let string = TokenTree::Literal(Literal::string(&self.tail));
             quote!(#output_ident.push_str(#string);)
         };
-        self.tail.clear();
         self.tokens.extend(push_str_expr);
+        if self.tail.len() >= LONG_TEXT_LEN {
+            self.tokens
+                .extend(self.flush_hook.clone().unwrap_or_default());
+        }
+        self.tail.clear();
     }
 
     fn finish(mut self) -> TokenStream {
@@ -9,35 +9,173 @@ mod ast;
 mod generate;
 mod parse;
 
-use proc_macro2::{Ident, Span, TokenStream, TokenTree};
-use proc_macro_error::proc_macro_error;
+use proc_macro2::{Delimiter, Group, Ident, Span, TokenStream, TokenTree};
+use proc_macro_error::{abort, proc_macro_error};
 use quote::quote;
+use syn::parse::{ParseStream, Parser};
+use syn::{Expr, Token};
 
 #[proc_macro]
 #[proc_macro_error]
 pub fn html(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    expand(input.into()).into()
+    expand(input.into(), false).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn html_static(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand_static(input.into()).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn html_pretty(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand(input.into(), true).into()
 }
 
 #[proc_macro]
 #[proc_macro_error]
 pub fn html_debug(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let expr = expand(input.into());
+    let expr = expand(input.into(), false);
     println!("expansion:\n{}", expr);
     expr.into()
 }
 
-fn expand(input: TokenStream) -> TokenStream {
+#[proc_macro]
+#[proc_macro_error]
+pub fn try_html(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand_try(input.into()).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn html_to(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand_to(input.into()).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn html_stream(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand_stream(input.into()).into()
+}
+
+fn expand(input: TokenStream, pretty: bool) -> TokenStream {
     let output_ident = TokenTree::Ident(Ident::new("__maud_output", Span::mixed_site()));
     // Heuristic: the size of the resulting markup tends to correlate with the
     // code size of the template itself
     let size_hint = input.to_string().len();
-    let markups = parse::parse(input);
-    let stmts = generate::generate(markups, output_ident.clone());
+    let (mut options, markups) = parse::parse(input);
+    options.pretty = pretty;
+    let stmts = generate::generate(options, markups, output_ident.clone(), None, false);
+    let trim = if pretty {
+        quote!(if #output_ident.starts_with('\n') {
+            #output_ident.remove(0);
+        })
+    } else {
+        TokenStream::new()
+    };
     quote!({
         extern crate maud;
         let mut #output_ident = ::std::string::String::with_capacity(#size_hint);
         #stmts
+        #trim
         maud::PreEscaped(#output_ident)
     })
 }
+
+fn expand_to(input: TokenStream) -> TokenStream {
+    let parse_sink = |input: ParseStream| {
+        let sink = input.parse::<Expr>()?;
+        input.parse::<Token![,]>()?;
+        Ok((sink, input.parse::<TokenStream>()?))
+    };
+    let (sink, mut input) = match parse_sink.parse2(input) {
+        Ok(parsed) => parsed,
+        Err(error) => abort!(
+            error.span(),
+            "expected an output before the markup";
+            help = "write `html_to!(output, {{ ... }})`"
+        ),
+    };
+    // Unwrap the braces in `html_to!(output, { ... })`, so that inner
+    // attributes still come first
+    if let [TokenTree::Group(group)] = &input.clone().into_iter().collect::<Vec<_>>()[..] {
+        if group.delimiter() == Delimiter::Brace {
+            input = group.stream();
+        }
+    }
+    let sink_ident = Ident::new("__maud_sink", Span::mixed_site());
+    // Every write borrows the buffer anew, so that the output can pass it
+    // on in between
+    let output_ident = TokenTree::Group(Group::new(
+        Delimiter::Parenthesis,
+        quote!(*maud::Output::buffer(&mut #sink_ident)),
+    ));
+    let (options, markups) = parse::parse(input);
+    let stmts = generate::generate(options, markups, output_ident, None, false);
+    quote!({
+        extern crate maud;
+        let mut #sink_ident = #sink;
+        #stmts
+        maud::Output::finish(&mut #sink_ident)
+    })
+}
+
+fn expand_stream(mut input: TokenStream) -> TokenStream {
+    // `html_stream!(move { ... })` moves the variables it uses into the
+    // stream, so that it can be returned from a function
+    let mut capture = TokenStream::new();
+    if let [TokenTree::Ident(keyword), TokenTree::Group(group)] =
+        &input.clone().into_iter().collect::<Vec<_>>()[..]
+    {
+        if keyword == "move" && group.delimiter() == Delimiter::Brace {
+            capture = quote!(#keyword);
+            input = group.stream();
+        }
+    }
+    let sink_ident = Ident::new("__maud_sink", Span::mixed_site());
+    let stream_ident = Ident::new("__maud_stream", Span::mixed_site());
+    let output_ident = TokenTree::Group(Group::new(
+        Delimiter::Parenthesis,
+        quote!(*maud::Output::buffer(&mut #sink_ident)),
+    ));
+    let flush_hook = quote!(#sink_ident.__maud_flush().await;);
+    let (options, markups) = parse::parse(input);
+    let stmts = generate::generate(options, markups, output_ident, Some(flush_hook), false);
+    // Without `move`, the template borrows the variables it uses, like
+    // `html!` does; only the output is moved in
+    quote!({
+        extern crate maud;
+        let (#stream_ident, #sink_ident) = maud::MarkupStream::__maud_new();
+        #stream_ident.__maud_start(async #capture {
+            let mut #sink_ident: maud::StreamOutput = #sink_ident;
+            #stmts
+            let _ = maud::Output::finish(&mut #sink_ident);
+        })
+    })
+}
+
+fn expand_try(input: TokenStream) -> TokenStream {
+    let output_ident = TokenTree::Ident(Ident::new("__maud_output", Span::mixed_site()));
+    let size_hint = input.to_string().len();
+    let (options, markups) = parse::parse(input);
+    let stmts = generate::generate(options, markups, output_ident.clone(), None, true);
+    // The closure gives `?` and failed splices something to return from
+    quote!({
+        extern crate maud;
+        (|| {
+            let mut #output_ident = ::std::string::String::with_capacity(#size_hint);
+            #stmts
+            ::std::result::Result::Ok(maud::PreEscaped(#output_ident))
+        })()
+    })
+}
+
+fn expand_static(input: TokenStream) -> TokenStream {
+    let (options, markups) = parse::parse(input);
+    let markup = generate::generate_static(options, markups);
+    quote!({
+        extern crate maud;
+        maud::PreEscaped(#markup)
+    })
+}
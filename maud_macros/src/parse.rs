@@ -2,12 +2,18 @@ use proc_macro2::{Delimiter, Ident, Literal, Spacing, Span, TokenStream, TokenTr
 use proc_macro_error::{abort, abort_call_site, emit_error, SpanRange};
 use std::collections::HashMap;
 
+use maud_htmlescape::{
+    is_valid_attribute_name, is_valid_element_name, Charset, InvalidChars, Profile, VOID_ELEMENTS,
+};
+use quote::quote;
 use syn::Lit;
 
 use crate::ast;
 
-pub fn parse(input: TokenStream) -> Vec<ast::Markup> {
-    Parser::new(input).markups()
+pub fn parse(input: TokenStream) -> (ast::Options, Vec<ast::Markup>) {
+    let mut parser = Parser::new(input);
+    let options = parser.options();
+    (options, parser.markups())
 }
 
 #[derive(Clone)]
@@ -62,6 +68,118 @@ impl Parser {
         self.next();
     }
 
+    /// Parses the inner attributes at the start of the input, such as
+    /// `#![escape = "xml"]`.
+    fn options(&mut self) -> ast::Options {
+        let mut options = ast::Options::default();
+        loop {
+            let group = match self.peek2() {
+                Some((TokenTree::Punct(ref hash), Some(TokenTree::Punct(ref bang))))
+                    if hash.as_char() == '#' && bang.as_char() == '!' =>
+                {
+                    self.advance2();
+                    match self.next() {
+                        Some(TokenTree::Group(ref group))
+                            if group.delimiter() == Delimiter::Bracket =>
+                        {
+                            group.clone()
+                        }
+                        _ => abort!(bang, "expected `[` after `#!`"),
+                    }
+                }
+                _ => break,
+            };
+            let mut tokens = group.stream().into_iter();
+            let (name, value) = match (tokens.next(), tokens.next(), tokens.next(), tokens.next()) {
+                (
+                    Some(TokenTree::Ident(name)),
+                    Some(TokenTree::Punct(ref eq)),
+                    Some(TokenTree::Literal(value)),
+                    None,
+                ) if eq.as_char() == '=' => (name, value),
+                _ => abort!(
+                    group,
+                    "invalid inner attribute";
+                    help = r#"inner attributes look like `#![escape = "xml"]`"#
+                ),
+            };
+            let value_string = match Lit::new(value.clone()) {
+                Lit::Str(lit_str) => lit_str.value(),
+                _ => abort!(value, "expected string"),
+            };
+            match name.to_string().as_str() {
+                "escape" => {
+                    options.profile = match value_string.as_str() {
+                        "minimal" => Profile::Minimal,
+                        "xml" => Profile::Xml,
+                        "strict" => Profile::Strict,
+                        _ => {
+                            emit_error!(
+                                value,
+                                "unknown escape profile `{}`", value_string;
+                                help = r#"expected one of "minimal", "xml" or "strict""#
+                            );
+                            continue;
+                        }
+                    };
+                }
+                "charset" => {
+                    let charset = match value_string.as_str() {
+                        "utf-8" => Charset::UTF8,
+                        "ascii" => Charset::ASCII,
+                        _ => {
+                            emit_error!(
+                                value,
+                                "unknown charset `{}`", value_string;
+                                help = r#"expected "utf-8" or "ascii""#
+                            );
+                            continue;
+                        }
+                    };
+                    options.charset = charset.with_invalid_chars(options.charset.invalid_chars());
+                }
+                "invalid_chars" => {
+                    let invalid_chars = match value_string.as_str() {
+                        "keep" => InvalidChars::Keep,
+                        "strip" => InvalidChars::Strip,
+                        "replace" => InvalidChars::Replace,
+                        _ => {
+                            emit_error!(
+                                value,
+                                "unknown value `{}`", value_string;
+                                help = r#"expected one of "keep", "strip" or "replace""#
+                            );
+                            continue;
+                        }
+                    };
+                    options.charset = options.charset.with_invalid_chars(invalid_chars);
+                }
+                "url_schemes" => {
+                    let schemes = value_string
+                        .split_whitespace()
+                        .map(str::to_ascii_lowercase)
+                        .collect::<Vec<_>>();
+                    for scheme in &schemes {
+                        let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                            && scheme.bytes().all(|b| {
+                                b.is_ascii_alphanumeric() || b == b'+' || b == b'-' || b == b'.'
+                            });
+                        if !valid {
+                            emit_error!(
+                                value,
+                                "invalid URL scheme `{}`", scheme;
+                                help = "list the schemes without a trailing colon, separated by spaces"
+                            );
+                        }
+                    }
+                    options.url_schemes = Some(schemes);
+                }
+                other => emit_error!(name, "unknown inner attribute `{}`", other),
+            }
+        }
+        options
+    }
+
     /// Parses multiple blocks of markup.
     fn markups(&mut self) -> Vec<ast::Markup> {
         let mut result = Vec::new();
@@ -112,6 +230,7 @@ impl Parser {
                             "while" => self.while_expr(at_span, keyword),
                             "for" => self.for_expr(at_span, keyword),
                             "match" => self.match_expr(at_span, keyword),
+                            "element" => self.dynamic_element(at_span, keyword),
                             "let" => {
                                 let span = SpanRange {
                                     first: at_span,
@@ -176,7 +295,7 @@ impl Parser {
             TokenTree::Group(ref group) if group.delimiter() == Delimiter::Parenthesis => {
                 self.advance();
                 ast::Markup::Splice {
-                    expr: group.stream(),
+                    expr: self.splice(group.stream()),
                     outer_span: SpanRange::single_span(group.span()),
                 }
             }
@@ -193,6 +312,41 @@ impl Parser {
         markup
     }
 
+    /// Parses the contents of a splice.
+    ///
+    /// A splice may end with a format spec, as in `(price; ".2")`. This
+    /// is rewritten to `format_args!("{:.2}", price)`, which is written
+    /// straight to the output without allocating.
+    fn splice(&mut self, expr: TokenStream) -> TokenStream {
+        let tokens = expr.clone().into_iter().collect::<Vec<_>>();
+        let (expr, semi, spec) = match &tokens[..] {
+            [expr @ .., TokenTree::Punct(semi), TokenTree::Literal(spec)]
+                if semi.as_char() == ';' =>
+            {
+                (expr, semi, spec)
+            }
+            _ => return expr,
+        };
+        let spec_value = match Lit::new(spec.clone()) {
+            Lit::Str(lit_str) => lit_str.value(),
+            _ => abort!(spec, "expected a format spec string, like `\".2\"`"),
+        };
+        if spec_value.contains(['{', '}']) {
+            abort!(
+                spec,
+                "format spec must not contain braces";
+                help = "write only the part after the colon, e.g. `(price; \".2\")`"
+            );
+        }
+        if expr.is_empty() {
+            abort!(semi, "expected an expression before the format spec");
+        }
+        let mut format = Literal::string(&format!("{{:{}}}", spec_value));
+        format.set_span(spec.span());
+        let expr = expr.iter().cloned().collect::<TokenStream>();
+        quote!(::std::format_args!(#format, #expr))
+    }
+
     /// Parses a literal string.
     fn literal(&mut self, literal: Literal) -> ast::Markup {
         match Lit::new(literal.clone()) {
@@ -532,7 +686,82 @@ impl Parser {
             abort!(span, "unexpected element");
         }
         let attrs = self.attrs();
-        let body = match self.peek() {
+        let body = self.element_body();
+        ast::Markup::Element { name, attrs, body }
+    }
+
+    /// Parses an `@element` expression.
+    ///
+    /// The leading `@element` should already be consumed.
+    fn dynamic_element(&mut self, at_span: Span, keyword: TokenTree) -> ast::Markup {
+        let keyword_span = SpanRange {
+            first: at_span,
+            last: keyword.span(),
+        };
+        if self.current_attr.is_some() {
+            abort!(keyword_span, "unexpected element");
+        }
+        let (name, name_span) = match self.next() {
+            Some(TokenTree::Group(ref group)) if group.delimiter() == Delimiter::Parenthesis => {
+                (group.stream(), SpanRange::single_span(group.span()))
+            }
+            _ => abort!(
+                keyword_span,
+                "expected a parenthesized tag name after `@element`";
+                help = "write the tag name in parentheses, e.g. `@element(tag) { ... }`"
+            ),
+        };
+        let attrs = self.attrs();
+        let body = self.element_body();
+        if let Some(name) = quoted_element_name(&name) {
+            // A literal name is known now, so it's an ordinary element,
+            // with the same rules for end tags as at runtime
+            let name_string = ast::name_to_string(name.clone());
+            let is_void = VOID_ELEMENTS
+                .iter()
+                .any(|void| void.eq_ignore_ascii_case(&name_string));
+            return match (is_void, body) {
+                (true, ast::ElementBody::Block { block }) => {
+                    if !block.markups.is_empty() {
+                        abort!(
+                            block.outer_span,
+                            "void element `{}` can't have a body", name_string;
+                            help = "end the element with `;` instead"
+                        );
+                    }
+                    ast::Markup::Element {
+                        name,
+                        attrs,
+                        body: ast::ElementBody::Void {
+                            semi_span: block.outer_span,
+                        },
+                    }
+                }
+                (false, ast::ElementBody::Void { semi_span }) => ast::Markup::Element {
+                    name,
+                    attrs,
+                    body: ast::ElementBody::Block {
+                        block: ast::Block {
+                            markups: Vec::new(),
+                            outer_span: semi_span,
+                        },
+                    },
+                },
+                (_, body) => ast::Markup::Element { name, attrs, body },
+            };
+        }
+        ast::Markup::DynamicElement {
+            at_span: SpanRange::single_span(at_span),
+            name,
+            name_span,
+            attrs,
+            body,
+        }
+    }
+
+    /// Parses the body of an element, which is either a block or `;`.
+    fn element_body(&mut self) -> ast::ElementBody {
+        match self.peek() {
             Some(TokenTree::Punct(ref punct))
                 if punct.as_char() == ';' || punct.as_char() == '/' =>
             {
@@ -553,17 +782,37 @@ impl Parser {
                     );
                 }
             },
-        };
-        ast::Markup::Element { name, attrs, body }
+        }
     }
 
     /// Parses the attributes of an element.
     fn attrs(&mut self) -> Vec<ast::Attr> {
         let mut attrs = Vec::new();
         loop {
-            if let Some(name) = self.try_namespaced_name() {
+            if let Some(name) = self
+                .try_namespaced_name()
+                .or_else(|| self.try_quoted_name())
+            {
                 // Attribute
                 match self.peek() {
+                    // Optional attribute
+                    Some(TokenTree::Punct(ref punct))
+                        if punct.as_char() == '='
+                            && matches!(
+                                self.peek2(),
+                                Some((_, Some(TokenTree::Group(ref group))))
+                                    if group.delimiter() == Delimiter::Bracket
+                            ) =>
+                    {
+                        self.advance();
+                        let toggler = self.attr_toggler().expect("should be a bracket group");
+                        attrs.push(ast::Attr::Attribute {
+                            attribute: ast::Attribute {
+                                name,
+                                attr_type: ast::AttrType::Optional { toggler },
+                            },
+                        });
+                    }
                     // Non-empty attribute
                     Some(TokenTree::Punct(ref punct)) if punct.as_char() == '=' => {
                         self.advance();
@@ -602,9 +851,21 @@ impl Parser {
                     }
                 }
             } else {
-                match self.peek() {
+                match self.peek2() {
+                    // Attribute spread
+                    Some((TokenTree::Punct(ref first), Some(TokenTree::Punct(ref second))))
+                        if first.as_char() == '.' && second.as_char() == '.' =>
+                    {
+                        self.advance2();
+                        let dots_span = SpanRange {
+                            first: first.span(),
+                            last: second.span(),
+                        };
+                        let spread = self.spread(dots_span);
+                        attrs.push(ast::Attr::Spread { spread });
+                    }
                     // Class shorthand
-                    Some(TokenTree::Punct(ref punct)) if punct.as_char() == '.' => {
+                    Some((TokenTree::Punct(ref punct), _)) if punct.as_char() == '.' => {
                         self.advance();
                         let name = self.class_or_id_name();
                         let toggler = self.attr_toggler();
@@ -615,7 +876,7 @@ impl Parser {
                         });
                     }
                     // ID shorthand
-                    Some(TokenTree::Punct(ref punct)) if punct.as_char() == '#' => {
+                    Some((TokenTree::Punct(ref punct), _)) if punct.as_char() == '#' => {
                         self.advance();
                         let name = self.class_or_id_name();
                         attrs.push(ast::Attr::Id {
@@ -629,34 +890,45 @@ impl Parser {
             }
         }
 
-        let mut attr_map: HashMap<String, Vec<SpanRange>> = HashMap::new();
+        // The span of each attribute, and whether it came from a spread
+        let mut attr_map: HashMap<String, Vec<(SpanRange, bool)>> = HashMap::new();
         let mut has_class = false;
         for attr in &attrs {
+            if attr.is_class() {
+                if has_class {
+                    // Classes from every source are merged, so only
+                    // check the first one
+                    continue;
+                }
+                has_class = true;
+            }
             let name = match attr {
-                ast::Attr::Class { .. } => {
-                    if has_class {
-                        // Only check the first class to avoid spurious duplicates
-                        continue;
+                ast::Attr::Class { .. } => "class".to_string(),
+                ast::Attr::Id { .. } => "id".to_string(),
+                ast::Attr::Attribute { attribute } => ast::name_to_string(attribute.name.clone()),
+                ast::Attr::Spread { spread } => {
+                    // Most names are only known at runtime, where the
+                    // attributes written out in the template win. But
+                    // an array of literal names can be checked now.
+                    for (name, span) in spread_literal_names(&spread.expr) {
+                        attr_map.entry(name).or_default().push((span, true));
                     }
-                    has_class = true;
-                    "class".to_string()
+                    continue;
                 }
-                ast::Attr::Id { .. } => "id".to_string(),
-                ast::Attr::Attribute { attribute } => attribute
-                    .name
-                    .clone()
-                    .into_iter()
-                    .map(|token| token.to_string())
-                    .collect(),
             };
             let entry = attr_map.entry(name).or_default();
-            entry.push(attr.span());
+            entry.push((attr.span(), false));
         }
 
         for (name, spans) in attr_map {
             if spans.len() > 1 {
                 let mut spans = spans.into_iter();
-                let first_span = spans.next().expect("spans should be non-empty");
+                let (first_span, _) = spans.next().expect("spans should be non-empty");
+                for (span, from_spread) in spans {
+                    if from_spread {
+                        emit_error!(span, "duplicate attribute `{}`", name);
+                    }
+                }
                 abort!(first_span, "duplicate attribute `{}`", name);
             }
         }
@@ -664,6 +936,24 @@ impl Parser {
         attrs
     }
 
+    /// Parses the expression after the `..` of an attribute spread.
+    fn spread(&mut self, dots_span: SpanRange) -> ast::Spread {
+        match self.next() {
+            Some(TokenTree::Group(ref group)) if group.delimiter() == Delimiter::Parenthesis => {
+                ast::Spread {
+                    dots_span,
+                    expr: group.stream(),
+                    outer_span: SpanRange::single_span(group.span()),
+                }
+            }
+            _ => abort!(
+                dots_span,
+                "expected a parenthesized expression after `..`";
+                help = "write the attributes to spread in parentheses, e.g. `..(attrs)`"
+            ),
+        }
+    }
+
     /// Parses the name of a class or ID.
     fn class_or_id_name(&mut self) -> ast::Markup {
         if let Some(symbol) = self.try_name() {
@@ -715,6 +1005,39 @@ impl Parser {
         Some(result.into_iter().collect())
     }
 
+    /// Parses a quoted attribute name, such as `"@click"`.
+    ///
+    /// The name must be followed by `=` or a toggle, so that a string
+    /// after an element is still reported as a body without braces.
+    fn try_quoted_name(&mut self) -> Option<TokenStream> {
+        let literal = match self.peek2() {
+            Some((TokenTree::Literal(literal), Some(TokenTree::Punct(ref punct))))
+                if punct.as_char() == '=' =>
+            {
+                literal
+            }
+            Some((TokenTree::Literal(literal), Some(TokenTree::Group(ref group))))
+                if group.delimiter() == Delimiter::Bracket =>
+            {
+                literal
+            }
+            _ => return None,
+        };
+        let name = match Lit::new(literal.clone()) {
+            Lit::Str(lit_str) => lit_str.value(),
+            _ => return None,
+        };
+        self.advance();
+        if !is_valid_attribute_name(&name) {
+            emit_error!(
+                literal,
+                "invalid attribute name {:?}", name;
+                help = "attribute names can't be empty, or contain whitespace, control characters, quotes, or any of `&<>/=`"
+            );
+        }
+        Some(TokenStream::from(TokenTree::Literal(literal)))
+    }
+
     /// Parses a HTML element or attribute name, along with a namespace
     /// if necessary.
     fn try_namespaced_name(&mut self) -> Option<TokenStream> {
@@ -738,3 +1061,59 @@ impl Parser {
         }
     }
 }
+
+/// Returns the name of an `@element` if it's a string literal, after
+/// checking that it's a valid element name.
+fn quoted_element_name(name: &TokenStream) -> Option<TokenStream> {
+    let mut tokens = name.clone().into_iter();
+    let literal = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => literal,
+        _ => return None,
+    };
+    let value = match Lit::new(literal.clone()) {
+        Lit::Str(lit_str) => lit_str.value(),
+        _ => return None,
+    };
+    if !is_valid_element_name(&value) {
+        emit_error!(
+            literal,
+            "invalid element name {:?}", value;
+            help = "element names must start with an ASCII letter, followed by ASCII letters, digits, or any of `-_.:`"
+        );
+    }
+    Some(TokenStream::from(TokenTree::Literal(literal)))
+}
+
+/// Returns the names in a spread of the form `[("name", value), ...]`,
+/// or `&[...]`, along with their spans.
+///
+/// Any other expression returns no names.
+fn spread_literal_names(expr: &TokenStream) -> Vec<(String, SpanRange)> {
+    let mut tokens = expr.clone().into_iter().peekable();
+    if matches!(tokens.peek(), Some(TokenTree::Punct(punct)) if punct.as_char() == '&') {
+        tokens.next();
+    }
+    let items = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Group(group)), None) if group.delimiter() == Delimiter::Bracket => {
+            group.stream()
+        }
+        _ => return Vec::new(),
+    };
+    items
+        .into_iter()
+        .filter_map(|item| match item {
+            TokenTree::Group(tuple) if tuple.delimiter() == Delimiter::Parenthesis => {
+                match tuple.stream().into_iter().next() {
+                    Some(TokenTree::Literal(literal)) => match Lit::new(literal.clone()) {
+                        Lit::Str(lit_str) => {
+                            Some((lit_str.value(), SpanRange::single_span(literal.span())))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .collect()
+}